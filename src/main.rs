@@ -5,13 +5,19 @@ mod utils;
 use bevy::prelude::*;
 use bevy_fly_camera::{FlyCamera, FlyCameraPlugin};
 use crate::map::MapGenerationPlugin;
-use crate::map::render::StaticVoxelRenderPlugin;
+use crate::map::far_terrain::FAR_TERRAIN_DISTANCE;
+use crate::map::chunk::BlockRegistryPlugin;
+use crate::map::render::{GreedyMesher, StaticVoxelRenderPlugin};
 use std::env;
 use crate::player::YamcPlayerPlugin;
 
 fn setup_camera(mut commands: Commands) {
     commands
         .spawn(Camera3d::default())
+        .insert(Projection::from(PerspectiveProjection {
+            far: FAR_TERRAIN_DISTANCE,
+            ..default()
+        }))
         .insert(FlyCamera::default());
 }
 
@@ -26,7 +32,8 @@ fn main() {
         .add_systems(Startup, setup_camera)
         .add_plugins(FlyCameraPlugin)
         .add_plugins(YamcPlayerPlugin)
-        .add_plugins(MapGenerationPlugin {seed: 1337} )
-        .add_plugins(StaticVoxelRenderPlugin)
+        .add_plugins(BlockRegistryPlugin::default())
+        .add_plugins(MapGenerationPlugin::with_config_file(1337, "assets/worldgen.ron", "saves/world"))
+        .add_plugins(StaticVoxelRenderPlugin::with_mesher(GreedyMesher))
         .run();
 }
\ No newline at end of file
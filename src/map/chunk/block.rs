@@ -1,14 +1,49 @@
 use super::block_type::BlockType;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Block {
     pub block_type: &'static BlockType,
+    pub state: BlockState,
+}
+
+/// Values of the block state properties packed as laid out by [`BlockType::states`].
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct BlockState(pub u16);
+
+#[derive(Debug)]
+pub struct InvalidBlockStateError();
+
+impl Block {
+    pub fn get_state(&self, name: &str) -> Option<u8> {
+        let (shift, property) = self.block_type.get_state_property(name)?;
+        Some(((self.state.0 >> shift) & property.mask()) as u8)
+    }
+
+    /// Copy of the block with the property `name` set to `value`.
+    pub fn with_state(self, name: &str, value: u8) -> Result<Block, InvalidBlockStateError> {
+        let (shift, property) = self.block_type.get_state_property(name).ok_or(InvalidBlockStateError())?;
+        if value >= property.values {
+            return Err(InvalidBlockStateError());
+        }
+
+        let cleared = self.state.0 & !(property.mask() << shift);
+        Ok(Block {
+            block_type: self.block_type,
+            state: BlockState(cleared | ((value as u16) << shift)),
+        })
+    }
+
+    /// Block id and state packed into a single number for serialization.
+    pub fn to_raw(&self) -> u32 {
+        ((self.block_type.id() as u32) << 16) | self.state.0 as u32
+    }
 }
 
 impl From<&'static BlockType> for Block {
     fn from(value: &'static BlockType) -> Self {
         Block {
-            block_type: value
+            block_type: value,
+            state: BlockState::default(),
         }
     }
 }
@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use bevy::color::Srgba;
+use bevy::prelude::*;
+use serde::Deserialize;
+use super::{Block, BlockState, BlockTextures, BlockType, StateProperty};
+
+/// Registers the built-in blocks and the ones described in the definitions file.
+pub struct BlockRegistryPlugin {
+    pub definitions: PathBuf,
+}
+
+impl Default for BlockRegistryPlugin {
+    fn default() -> Self {
+        BlockRegistryPlugin {
+            definitions: PathBuf::from("assets/blocks.ron"),
+        }
+    }
+}
+
+impl Plugin for BlockRegistryPlugin {
+    fn build(&self, app: &mut App) {
+        let mut registry = BlockRegistry::new();
+        match registry.load_definitions(&self.definitions) {
+            Ok(count) => info!("Loaded {count} block definitions from {:?}", self.definitions),
+            Err(err) => warn!("Failed to load block definitions from {:?}: {err:?}", self.definitions),
+        }
+        app.insert_resource(registry);
+    }
+}
+
+/// All known block types by their id and by their name.
+///
+/// Block types are leaked on registration, so they live for the rest of the program
+/// just like the built-in constants and can be referenced by [`super::Block`].
+/// The tables are shared, so clones handed to generation tasks are cheap.
+#[derive(Resource, Clone)]
+pub struct BlockRegistry {
+    types: Arc<Vec<Option<&'static BlockType>>>,
+    by_name: Arc<HashMap<String, usize>>,
+}
+
+#[derive(Debug)]
+pub enum BlockRegistryError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    InvalidColor(String),
+    InvalidLightEmission(u8),
+    InvalidId(usize),
+    InvalidStates(String),
+    InvalidTextures(String),
+    DuplicateId(usize),
+    DuplicateName(String),
+}
+
+/// Entry of the block definitions file.
+#[derive(Deserialize)]
+struct BlockDefinition {
+    id: usize,
+    name: String,
+    /// Hex string like `"#FFAA00"`.
+    color: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default = "default_solid")]
+    solid: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    fluid: bool,
+    #[serde(default)]
+    light_emission: u8,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+    #[serde(default = "default_hardness")]
+    blast_resistance: f32,
+    #[serde(default)]
+    states: Vec<StateDefinition>,
+    /// Texture file names by face: `"top"`, `"side"` and `"bottom"`, or `"all"` for every face.
+    #[serde(default)]
+    textures: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct StateDefinition {
+    name: String,
+    values: u8,
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+impl BlockRegistry {
+    /// Registry holding only [`BlockType::BUILTIN`] blocks.
+    pub fn new() -> Self {
+        let mut registry = BlockRegistry {
+            types: Arc::new(Vec::new()),
+            by_name: Arc::new(HashMap::new()),
+        };
+        for block_type in BlockType::BUILTIN {
+            registry.register(block_type).expect("Built-in blocks must have unique ids and names");
+        }
+        registry
+    }
+
+    pub fn register(&mut self, block_type: &'static BlockType) -> Result<(), BlockRegistryError> {
+        self.check(block_type)?;
+        let types = Arc::make_mut(&mut self.types);
+        if types.len() <= block_type.id {
+            types.resize(block_type.id + 1, None);
+        }
+        types[block_type.id] = Some(block_type);
+        Arc::make_mut(&mut self.by_name).insert(block_type.name.to_lowercase(), block_type.id);
+        Ok(())
+    }
+
+    /// Whether `block_type` can be registered next to the blocks already in the registry.
+    /// Id `u16::MAX` is reserved, so no [`Block::to_raw`] is ever `u32::MAX`.
+    fn check(&self, block_type: &BlockType) -> Result<(), BlockRegistryError> {
+        if block_type.id >= u16::MAX as usize {
+            return Err(BlockRegistryError::InvalidId(block_type.id));
+        }
+        if block_type.state_bits() > BlockType::MAX_STATE_BITS || block_type.states.iter().any(|s| s.values == 0) {
+            return Err(BlockRegistryError::InvalidStates(block_type.name.to_string()));
+        }
+        if self.get(block_type.id).is_some() {
+            return Err(BlockRegistryError::DuplicateId(block_type.id));
+        }
+        if self.by_name.contains_key(&block_type.name.to_lowercase()) {
+            return Err(BlockRegistryError::DuplicateName(block_type.name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Registers every block of a RON definitions file. Returns the number of registered blocks.
+    pub fn load_definitions(&mut self, path: &Path) -> Result<usize, BlockRegistryError> {
+        let content = std::fs::read_to_string(path).map_err(BlockRegistryError::Io)?;
+        self.load_definitions_str(&content)
+    }
+
+    /// Registers the blocks only if all of them are valid, the registry is left untouched otherwise.
+    pub fn load_definitions_str(&mut self, content: &str) -> Result<usize, BlockRegistryError> {
+        let definitions: Vec<BlockDefinition> = ron::from_str(content).map_err(BlockRegistryError::Parse)?;
+        let block_types = definitions.into_iter()
+            .map(BlockDefinition::into_block_type)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut ids = HashSet::new();
+        let mut names = HashSet::new();
+        for block_type in &block_types {
+            self.check(block_type)?;
+            if !ids.insert(block_type.id) {
+                return Err(BlockRegistryError::DuplicateId(block_type.id));
+            }
+            if !names.insert(block_type.name.to_lowercase()) {
+                return Err(BlockRegistryError::DuplicateName(block_type.name.to_string()));
+            }
+        }
+
+        let count = block_types.len();
+        for block_type in block_types {
+            self.register(Box::leak(Box::new(block_type))).expect("Definitions are checked before registering");
+        }
+        Ok(count)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&'static BlockType> {
+        self.types.get(id).copied().flatten()
+    }
+
+    /// Inverse of [`Block::to_raw`]. `None` for unknown blocks and states the block doesn't have.
+    pub fn get_block_from_raw(&self, raw: u32) -> Option<Block> {
+        let block_type = self.get((raw >> 16) as usize)?;
+        let state = BlockState(raw as u16);
+        block_type.is_valid_state(state).then_some(Block { block_type, state })
+    }
+
+    /// Case-insensitive lookup by [`BlockType::name`].
+    pub fn get_by_name(&self, name: &str) -> Option<&'static BlockType> {
+        self.get(*self.by_name.get(&name.to_lowercase())?)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'static BlockType> + '_ {
+        self.types.iter().flatten().copied()
+    }
+
+    pub fn iter_tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'static BlockType> + 'a {
+        self.iter().filter(move |block_type| block_type.has_tag(tag))
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockDefinition {
+    fn into_block_type(self) -> Result<BlockType, BlockRegistryError> {
+        let color = Srgba::hex(&self.color)
+            .map_err(|_| BlockRegistryError::InvalidColor(self.color.clone()))?;
+        if self.light_emission > BlockType::MAX_LIGHT {
+            return Err(BlockRegistryError::InvalidLightEmission(self.light_emission));
+        }
+        let tags: Vec<&'static str> = self.tags.into_iter()
+            .map(|tag| &*Box::leak(tag.into_boxed_str()))
+            .collect();
+        let textures = Self::parse_textures(&self.name, self.textures)?;
+        let states: Vec<StateProperty> = self.states.into_iter()
+            .map(|state| StateProperty {
+                name: Box::leak(state.name.into_boxed_str()),
+                values: state.values,
+            })
+            .collect();
+
+        Ok(BlockType {
+            id: self.id,
+            name: Box::leak(self.name.into_boxed_str()),
+            color: Color::Srgba(color),
+            tags: Box::leak(tags.into_boxed_slice()),
+            is_solid: self.solid,
+            is_opaque: !self.transparent,
+            is_fluid: self.fluid,
+            light_emission: self.light_emission,
+            hardness: self.hardness,
+            blast_resistance: self.blast_resistance,
+            states: Box::leak(states.into_boxed_slice()),
+            textures,
+        })
+    }
+
+    fn parse_textures(name: &str, mut textures: HashMap<String, String>) -> Result<Option<BlockTextures>, BlockRegistryError> {
+        if textures.is_empty() {
+            return Ok(None);
+        }
+
+        let all = textures.remove("all");
+        let mut face = |face: &str| textures.remove(face)
+            .or_else(|| all.clone())
+            .map(|file| &*Box::leak(file.into_boxed_str()))
+            .ok_or_else(|| BlockRegistryError::InvalidTextures(format!("{name}: no {face} texture")));
+        let block_textures = BlockTextures {
+            top: face("top")?,
+            side: face("side")?,
+            bottom: face("bottom")?,
+        };
+        if let Some(unknown) = textures.keys().next() {
+            return Err(BlockRegistryError::InvalidTextures(format!("{name}: unknown face {unknown}")));
+        }
+        Ok(Some(block_textures))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_are_registered_all_or_nothing() {
+        let mut registry = BlockRegistry::new();
+        let builtin = registry.iter().count();
+        let result = registry.load_definitions_str(r##"[
+            (id: 100, name: "Marble", color: "#EEEEEE"),
+            (id: 101, name: "Granite", color: "not a color"),
+        ]"##);
+        assert!(matches!(result, Err(BlockRegistryError::InvalidColor(_))));
+        assert_eq!(registry.iter().count(), builtin);
+        assert!(registry.get_by_name("marble").is_none());
+
+        let result = registry.load_definitions_str(r##"[
+            (id: 100, name: "Marble", color: "#EEEEEE"),
+            (id: 100, name: "Granite", color: "#888888"),
+        ]"##);
+        assert!(matches!(result, Err(BlockRegistryError::DuplicateId(100))));
+        assert!(registry.get(100).is_none());
+
+        let result = registry.load_definitions_str(r##"[
+            (id: 100, name: "Marble", color: "#EEEEEE"),
+            (id: 101, name: "Granite", color: "#888888"),
+        ]"##);
+        assert!(matches!(result, Ok(2)));
+        assert_eq!(registry.get_by_name("granite").map(BlockType::id), Some(101));
+    }
+
+    #[test]
+    fn raw_blocks_keep_only_existing_states() {
+        let mut registry = BlockRegistry::new();
+        registry.load_definitions_str(r##"[
+            (id: 100, name: "Door", color: "#885522", states: [(name: "open", values: 2), (name: "facing", values: 3)]),
+        ]"##).unwrap();
+        let door = registry.get(100).unwrap();
+
+        let block = Block::from(door).with_state("facing", 2).unwrap().with_state("open", 1).unwrap();
+        assert!(registry.get_block_from_raw(block.to_raw()) == Some(block));
+        // `facing` has no fourth value.
+        assert!(registry.get_block_from_raw(100 << 16 | 0b110).is_none());
+        // Past the three state bits.
+        assert!(registry.get_block_from_raw(100 << 16 | 0b1000).is_none());
+        assert!(registry.get_block_from_raw((BlockType::STONE.id() as u32) << 16 | 1).is_none());
+    }
+
+    #[test]
+    fn id_of_air_is_reserved() {
+        let result = BlockRegistry::new().load_definitions_str(r##"[(id: 65535, name: "Void", color: "#000000")]"##);
+        assert!(matches!(result, Err(BlockRegistryError::InvalidId(65535))));
+    }
+}
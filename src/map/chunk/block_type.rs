@@ -1,11 +1,53 @@
 use bevy::prelude::Color;
+use bevy::color::Srgba;
 use bevy::color::palettes::*;
+use super::BlockState;
 
 #[derive(Clone)]
 pub struct BlockType {
-    id: usize,
+    pub(super) id: usize,
     pub name: &'static str,
+    /// Tint of the block, blocks that aren't opaque are blended by its alpha.
     pub color: Color,
+    pub tags: &'static [&'static str],
+    pub is_solid: bool,
+    pub is_opaque: bool,
+    pub is_fluid: bool,
+    /// Light level emitted by the block, 0 to 15.
+    pub light_emission: u8,
+    /// How long the block takes to break.
+    pub hardness: f32,
+    /// How well the block resists explosions.
+    pub blast_resistance: f32,
+    /// Properties of the block state, packed into [`super::BlockState`] in this order.
+    pub states: &'static [StateProperty],
+    /// Textures of the faces, blocks without them are drawn with [`Self::color`].
+    pub textures: Option<BlockTextures>,
+}
+
+/// File names of face textures, relative to the block textures directory.
+#[derive(Copy, Clone, Debug)]
+pub struct BlockTextures {
+    pub top: &'static str,
+    pub side: &'static str,
+    pub bottom: &'static str,
+}
+
+/// Named block state property taking values from `0` to `values - 1`.
+#[derive(Copy, Clone, Debug)]
+pub struct StateProperty {
+    pub name: &'static str,
+    pub values: u8,
+}
+
+impl StateProperty {
+    pub fn bits(&self) -> u32 {
+        u8::BITS - self.values.saturating_sub(1).leading_zeros()
+    }
+
+    pub fn mask(&self) -> u16 {
+        (1u16 << self.bits()) - 1
+    }
 }
 
 impl PartialEq for BlockType {
@@ -22,77 +64,203 @@ impl BlockType {
     pub const ICE: BlockType = BlockType {
         id: 3,
         name: "Ice",
-        color: Color::Srgba(css::AQUAMARINE),
+        color: Color::Srgba(Srgba { alpha: 0.8, ..css::AQUAMARINE }),
+        tags: &["topping"],
+        is_solid: true,
+        is_opaque: false,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: 0.5,
+        blast_resistance: 0.5,
+        states: &[],
+        textures: None,
     };
 
     pub const STONE: BlockType = BlockType {
         id: 1,
         name: "Stone",
         color: Color::Srgba(css::DARK_GRAY),
+        tags: &["crust"],
+        is_solid: true,
+        is_opaque: true,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: 1.5,
+        blast_resistance: 6.0,
+        states: &[],
+        textures: None,
     };
 
     pub const DIRT: BlockType = BlockType {
         id: 2,
         name: "Dirt",
         color: Color::Srgba(css::GREEN),
+        tags: &["topping"],
+        is_solid: true,
+        is_opaque: true,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: 0.5,
+        blast_resistance: 0.5,
+        states: &[],
+        textures: None,
     };
 
     pub const FOREST_DIRT: BlockType = BlockType {
         id: 4,
         name: "ForestDirt",
         color: Color::Srgba(css::DARK_GREEN),
+        tags: &["topping"],
+        is_solid: true,
+        is_opaque: true,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: 0.6,
+        blast_resistance: 0.6,
+        states: &[],
+        textures: None,
     };
 
     pub const WATER: BlockType = BlockType {
         id: 5,
         name: "Water",
-        color: Color::Srgba(css::NAVY),
+        color: Color::Srgba(Srgba { alpha: 0.6, ..css::NAVY }),
+        tags: &["fluid"],
+        is_solid: false,
+        is_opaque: false,
+        is_fluid: true,
+        light_emission: 0,
+        hardness: 100.0,
+        blast_resistance: 100.0,
+        states: &[StateProperty { name: "level", values: 8 }],
+        textures: None,
     };
 
     pub const SAND: BlockType = BlockType {
         id: 6,
         name: "Sand",
         color: Color::Srgba(css::GOLD),
+        tags: &["topping"],
+        is_solid: true,
+        is_opaque: true,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: 0.5,
+        blast_resistance: 0.5,
+        states: &[],
+        textures: None,
     };
 
     pub const IRON: BlockType = BlockType {
         id: 7,
         name: "Iron",
         color: Color::Srgba(css::GRAY),
+        tags: &["resource"],
+        is_solid: true,
+        is_opaque: true,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: 3.0,
+        blast_resistance: 3.0,
+        states: &[],
+        textures: None,
     };
 
     pub const COPPER: BlockType = BlockType {
         id: 8,
         name: "Copper",
         color: Color::Srgba(css::ORANGE),
+        tags: &["resource"],
+        is_solid: true,
+        is_opaque: true,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: 3.0,
+        blast_resistance: 3.0,
+        states: &[],
+        textures: None,
     };
 
     pub const COAL: BlockType = BlockType {
         id: 9,
         name: "Coal",
         color: Color::BLACK,
+        tags: &["resource"],
+        is_solid: true,
+        is_opaque: true,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: 3.0,
+        blast_resistance: 3.0,
+        states: &[],
+        textures: None,
     };
 
     pub const UNBREAKABLE: BlockType = BlockType {
         id: 0,
         name: "Unbreakable",
         color: Color::BLACK,
+        tags: &["crust"],
+        is_solid: true,
+        is_opaque: true,
+        is_fluid: false,
+        light_emission: 0,
+        hardness: f32::INFINITY,
+        blast_resistance: f32::INFINITY,
+        states: &[],
+        textures: None,
     };
 
+    pub const MAX_LIGHT: u8 = 15;
+    pub const MAX_STATE_BITS: u32 = u16::BITS;
+
+    pub const BUILTIN: [&'static BlockType; 10] = [
+        &BlockType::UNBREAKABLE,
+        &BlockType::STONE,
+        &BlockType::DIRT,
+        &BlockType::ICE,
+        &BlockType::FOREST_DIRT,
+        &BlockType::WATER,
+        &BlockType::SAND,
+        &BlockType::IRON,
+        &BlockType::COPPER,
+        &BlockType::COAL,
+    ];
+
     pub fn id(&self) -> usize {
         self.id
     }
-}
 
-pub struct BlockKind(Vec<BlockType>);
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(&tag)
+    }
+
+    /// State property called `name` together with its offset in the packed state.
+    pub fn get_state_property(&self, name: &str) -> Option<(u32, &StateProperty)> {
+        let mut shift = 0;
+        for property in self.states {
+            if property.name == name {
+                return Some((shift, property));
+            }
+            shift += property.bits();
+        }
+        None
+    }
 
-impl BlockKind {
-    pub const RESOURCES: [BlockType; 3] = [BlockType::IRON, BlockType::COPPER, BlockType::COAL];
-    pub const CRUST: [BlockType; 2] = [BlockType::STONE, BlockType::UNBREAKABLE];
-    pub const TOPPING: [BlockType; 4] = [BlockType::ICE, BlockType::DIRT, BlockType::FOREST_DIRT, BlockType::SAND];
-    pub const FLUID: [BlockType; 1] = [BlockType::WATER];
+    pub fn state_bits(&self) -> u32 {
+        self.states.iter().map(StateProperty::bits).sum()
+    }
 
-    pub fn contains(&self, block_type: &BlockType) -> bool {
-        self.0.contains(block_type)
+    /// Whether every property of the packed state is in range and no bits are set past them.
+    pub fn is_valid_state(&self, state: BlockState) -> bool {
+        if (state.0 as u32) >> self.state_bits() != 0 {
+            return false;
+        }
+        let mut shift = 0;
+        self.states.iter().all(|property| {
+            let value = (state.0 >> shift) & property.mask();
+            shift += property.bits();
+            value < property.values as u16
+        })
     }
 }
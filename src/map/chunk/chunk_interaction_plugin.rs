@@ -13,6 +13,9 @@ impl Plugin for ChunkInteractionPlugin {
 fn reset_updated(mut chunks: Query<&mut Chunk>)
 {
     for mut chunk in chunks.iter_mut() {
-        chunk.is_updated = false;
+        if chunk.is_updated {
+            // Resetting the flag is not a change of the chunk.
+            chunk.bypass_change_detection().reset_updated();
+        }
     }
 }
\ No newline at end of file
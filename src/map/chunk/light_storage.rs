@@ -0,0 +1,80 @@
+use super::BlockType;
+
+/// Light level of a cell: sky light in the high nibble, block light in the low one.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Light(pub u8);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LightChannel {
+    /// Light coming from the sky, it goes down without fading.
+    Sky,
+    /// Light emitted by blocks.
+    Block,
+}
+
+impl LightChannel {
+    pub const ALL: [LightChannel; 2] = [LightChannel::Sky, LightChannel::Block];
+}
+
+impl Light {
+    pub const DARK: Light = Light(0);
+    pub const SKY: Light = Light(BlockType::MAX_LIGHT << 4);
+
+    pub fn get(self, channel: LightChannel) -> u8 {
+        match channel {
+            LightChannel::Sky => self.0 >> 4,
+            LightChannel::Block => self.0 & 0xF,
+        }
+    }
+
+    pub fn with(self, channel: LightChannel, level: u8) -> Light {
+        let level = level.min(BlockType::MAX_LIGHT);
+        match channel {
+            LightChannel::Sky => Light((self.0 & 0xF) | (level << 4)),
+            LightChannel::Block => Light((self.0 & 0xF0) | level),
+        }
+    }
+
+    /// The brighter of the two channels.
+    pub fn level(self) -> u8 {
+        self.get(LightChannel::Sky).max(self.get(LightChannel::Block))
+    }
+}
+
+/// Light of every cell of a chunk.
+///
+/// Chunks fully in the sky or fully underground are lit evenly, so the cells are
+/// only allocated once some of them differ.
+#[derive(Clone)]
+pub struct LightStorage {
+    uniform: Light,
+    cells: Option<Box<[Light]>>,
+    len: usize,
+}
+
+impl LightStorage {
+    pub fn new(len: usize, light: Light) -> Self {
+        LightStorage {
+            uniform: light,
+            cells: None,
+            len,
+        }
+    }
+
+    pub unsafe fn get_unchecked(&self, i: usize) -> Light {
+        match &self.cells {
+            Some(cells) => *cells.get_unchecked(i),
+            None => self.uniform,
+        }
+    }
+
+    /// Returns whether the light of the cell has changed.
+    pub unsafe fn set_unchecked(&mut self, i: usize, light: Light) -> bool {
+        if self.get_unchecked(i) == light {
+            return false;
+        }
+        let cells = self.cells.get_or_insert_with(|| vec![self.uniform; self.len].into_boxed_slice());
+        *cells.get_unchecked_mut(i) = light;
+        true
+    }
+}
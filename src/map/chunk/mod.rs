@@ -1,18 +1,31 @@
 mod block;
 mod block_type;
+mod block_registry;
 mod chunk_interaction_plugin;
+mod palette_storage;
+mod light_storage;
 
-use std::iter::{Enumerate, Map};
-use std::slice::{Iter, IterMut};
+use std::sync::Arc;
 use bevy::prelude::*;
 pub use block::*;
 pub use block_type::*;
-use crate::utils::{BlockPos, CHUNK_SIZE};
+pub use block_registry::*;
+pub use palette_storage::PaletteStorage;
+pub use light_storage::{Light, LightChannel, LightStorage};
+pub use chunk_interaction_plugin::ChunkInteractionPlugin;
+use crate::utils::{BlockPos, CHUNK_SIZE, NEIGHBOUR_OFFSETS};
 
+/// Blocks and light are shared between clones and copied on the first write,
+/// so snapshots of chunks taken for meshing are cheap.
 #[derive(Component, Clone)]
 pub struct Chunk {
-    blocks: Vec<Option<Block>>,
+    blocks: Arc<PaletteStorage>,
+    light: Arc<LightStorage>,
     pub is_updated: bool,
+    /// Sides of the chunk touched by the updates, bits in [`NEIGHBOUR_OFFSETS`] order.
+    updated_borders: u8,
+    /// Whether the chunk differs from its saved or generated version.
+    pub is_modified: bool,
     amount_of_blocks: usize,
 }
 
@@ -22,44 +35,144 @@ pub struct SpawnInOccupiedSpaceError();
 #[derive(Debug)]
 pub struct PositionNotInChunkError();
 
+#[derive(Debug)]
+pub struct InvalidChunkSizeError();
+
 impl Chunk {
     pub const SIZE: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
 
     pub fn new() -> Self {
         Chunk {
-            blocks: vec![None; Self::SIZE],
+            blocks: Arc::new(PaletteStorage::new(Self::SIZE)),
+            light: Arc::new(LightStorage::new(Self::SIZE, Light::DARK)),
             is_updated: false,
+            updated_borders: 0,
+            is_modified: false,
             amount_of_blocks: 0,
         }
     }
 
+    pub fn from_storage(blocks: PaletteStorage) -> Result<Self, InvalidChunkSizeError> {
+        if blocks.len() != Self::SIZE {
+            return Err(InvalidChunkSizeError());
+        }
+
+        let amount_of_blocks = blocks.iter().filter(|b| b.is_some()).count();
+        Ok(Chunk {
+            blocks: Arc::new(blocks),
+            light: Arc::new(LightStorage::new(Self::SIZE, Light::DARK)),
+            is_updated: false,
+            updated_borders: 0,
+            is_modified: false,
+            amount_of_blocks,
+        })
+    }
+
+    pub fn storage(&self) -> &PaletteStorage {
+        &self.blocks
+    }
+
     pub unsafe fn spawn_block_unchecked(&mut self, pos: &BlockPos, value: &'static BlockType) {
-        let placed_mut = self.blocks.get_unchecked_mut(pos.to_index());
-        *placed_mut = Some(value.into());
-        self.amount_of_blocks += 1;
+        let previous = Arc::make_mut(&mut self.blocks).set_unchecked(pos.to_index(), Some(value.into()));
+        if previous.is_none() {
+            self.amount_of_blocks += 1;
+        }
     }
 
-    pub fn get_amount_of_blocks(&self) -> usize {
-        self.amount_of_blocks
+    /// Places a block at `pos`, overwriting whatever was there. Returns the previous block.
+    pub fn set_block(&mut self, pos: &BlockPos, value: &'static BlockType) -> Result<Option<Block>, PositionNotInChunkError> {
+        self.replace_block(pos, Some(value.into()))
     }
 
-    pub fn iter(&self) -> Iter<'_, Option<Block>> {
-        self.blocks.iter()
+    /// Removes the block at `pos`. Returns the removed block.
+    pub fn remove_block(&mut self, pos: &BlockPos) -> Result<Option<Block>, PositionNotInChunkError> {
+        self.replace_block(pos, None)
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<'_, Option<Block>> {
-        self.blocks.iter_mut()
+    /// Puts `value` at `pos` and returns the previous block.
+    /// Marks the chunk as updated if the block has actually changed.
+    pub fn replace_block(&mut self, pos: &BlockPos, value: Option<Block>) -> Result<Option<Block>, PositionNotInChunkError> {
+        if !pos.is_valid() {
+            return Err(PositionNotInChunkError());
+        }
+
+        let previous = unsafe { *self.get_unchecked(pos) };
+        if previous == value {
+            return Ok(previous);
+        }
+        unsafe {
+            Arc::make_mut(&mut self.blocks).set_unchecked(pos.to_index(), value);
+        }
+
+        match (previous, value) {
+            (None, Some(_)) => self.amount_of_blocks += 1,
+            (Some(_), None) => self.amount_of_blocks -= 1,
+            _ => {}
+        }
+        self.is_modified = true;
+        self.mark_updated(pos);
+        Ok(previous)
     }
 
-    pub fn iter_with_pos(&self) -> Map<Enumerate<Iter<Option<Block>>>, fn((usize, &Option<Block>)) -> (BlockPos, &Option<Block>)> {
-        self.blocks.iter()
-            .enumerate()
-            .map(|(i, block)| (BlockPos::from_index(i), block))
+    fn mark_updated(&mut self, pos: &BlockPos) {
+        self.is_updated = true;
+        for (side, offset) in NEIGHBOUR_OFFSETS.iter().enumerate() {
+            if !BlockPos(pos.0 + *offset).is_valid() {
+                self.updated_borders |= 1 << side;
+            }
+        }
+    }
+
+    pub fn get_light_at(&self, pos: &BlockPos) -> Result<Light, PositionNotInChunkError> {
+        if !pos.is_valid() {
+            return Err(PositionNotInChunkError());
+        }
+        unsafe {
+            Ok(self.light.get_unchecked(pos.to_index()))
+        }
     }
 
+    /// Marks the chunk as updated if the light has actually changed.
+    /// Light is derived from the blocks, so it doesn't make the chunk modified.
+    pub fn set_light(&mut self, pos: &BlockPos, light: Light) -> Result<(), PositionNotInChunkError> {
+        if !pos.is_valid() {
+            return Err(PositionNotInChunkError());
+        }
+        if unsafe { self.light.get_unchecked(pos.to_index()) } == light {
+            return Ok(());
+        }
+        unsafe {
+            Arc::make_mut(&mut self.light).set_unchecked(pos.to_index(), light);
+        }
+        self.mark_updated(pos);
+        Ok(())
+    }
+
+    /// Sets the same light in every cell.
+    pub fn fill_light(&mut self, light: Light) {
+        self.light = Arc::new(LightStorage::new(Self::SIZE, light));
+    }
+
+    /// Whether an update touched blocks next to the neighbour at `NEIGHBOUR_OFFSETS[side]`.
+    pub fn is_border_updated(&self, side: usize) -> bool {
+        self.updated_borders & (1 << side) != 0
+    }
 
-    pub fn iter_mut_with_pos(&mut self) -> Map<Enumerate<IterMut<Option<Block>>>, fn((usize, &mut Option<Block>)) -> (BlockPos, &mut Option<Block>)> {
-        self.blocks.iter_mut()
+    pub fn reset_updated(&mut self) {
+        self.is_updated = false;
+        self.updated_borders = 0;
+    }
+
+    pub fn get_amount_of_blocks(&self) -> usize {
+        self.amount_of_blocks
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Option<Block>> + '_ {
+        self.blocks.iter()
+    }
+
+    pub fn iter_with_pos(&self) -> impl Iterator<Item = (BlockPos, &Option<Block>)> + '_ {
+        self.blocks.iter()
             .enumerate()
             .map(|(i, block)| (BlockPos::from_index(i), block))
     }
@@ -72,12 +185,28 @@ impl Chunk {
             Ok(self.get_unchecked(pos))
         }
     }
-    
+
     unsafe fn get_unchecked(&self, pos: &BlockPos) -> &Option<Block> {
         self.blocks.get_unchecked(pos.to_index())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_data_until_changed() {
+        let mut chunk = Chunk::new();
+        chunk.set_block(&BlockPos::new(1, 2, 3), &BlockType::STONE).unwrap();
+        let snapshot = chunk.clone();
+        assert!(std::ptr::eq(chunk.storage(), snapshot.storage()));
 
-    unsafe fn get_unchecked_mut(&mut self, pos: &BlockPos) -> &mut Option<Block> {
-        self.blocks.get_unchecked_mut(pos.to_index())
+        chunk.set_block(&BlockPos::new(1, 2, 3), &BlockType::DIRT).unwrap();
+        chunk.set_light(&BlockPos::new(0, 0, 0), Light(7)).unwrap();
+        assert!(!std::ptr::eq(chunk.storage(), snapshot.storage()));
+        assert!(*snapshot.get_block_at(&BlockPos::new(1, 2, 3)).unwrap() == Some(Block::from(&BlockType::STONE)));
+        assert_eq!(snapshot.get_light_at(&BlockPos::new(0, 0, 0)).unwrap(), Light::DARK);
+        assert_eq!(chunk.get_light_at(&BlockPos::new(0, 0, 0)).unwrap(), Light(7));
     }
 }
@@ -0,0 +1,205 @@
+use super::Block;
+
+const MIN_BITS: usize = 1;
+const MAX_BITS: usize = 16;
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Palette-compressed storage of blocks.
+///
+/// Every distinct block value is stored once in the palette, and every cell keeps only
+/// a bit-packed index into it. Indices never span two words, so each word holds
+/// `64 / bits` of them. Values no cell uses anymore are dropped from a full palette
+/// before it grows, so it never holds more entries than there are cells.
+#[derive(Clone)]
+pub struct PaletteStorage {
+    palette: Vec<Option<Block>>,
+    data: Vec<u64>,
+    bits: usize,
+    len: usize,
+}
+
+impl PaletteStorage {
+    pub fn new(len: usize) -> Self {
+        PaletteStorage {
+            palette: vec![None],
+            data: vec![0; Self::words_for(len, MIN_BITS)],
+            bits: MIN_BITS,
+            len,
+        }
+    }
+
+    /// Rebuilds the storage from the parts returned by [`Self::palette`], [`Self::bits`]
+    /// and [`Self::data`]. Returns `None` if they are inconsistent.
+    pub fn from_parts(palette: Vec<Option<Block>>, bits: usize, data: Vec<u64>, len: usize) -> Option<Self> {
+        if !(MIN_BITS..=MAX_BITS).contains(&bits)
+            || palette.is_empty()
+            || palette.len() > 1 << bits
+            || data.len() != Self::words_for(len, bits) {
+            return None;
+        }
+
+        let storage = PaletteStorage { palette, data, bits, len };
+        if (0..len).any(|i| storage.get_index(i) >= storage.palette.len()) {
+            return None;
+        }
+        Some(storage)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    pub fn palette(&self) -> &[Option<Block>] {
+        &self.palette
+    }
+
+    pub fn data(&self) -> &[u64] {
+        &self.data
+    }
+
+    pub unsafe fn get_unchecked(&self, i: usize) -> &Option<Block> {
+        self.palette.get_unchecked(self.get_index(i))
+    }
+
+    /// Stores `value` at `i` and returns the value that was there before.
+    pub unsafe fn set_unchecked(&mut self, i: usize, value: Option<Block>) -> Option<Block> {
+        let palette_index = self.palette_index_of(value);
+        let previous = self.get_index(i);
+        self.set_index(i, palette_index);
+        *self.palette.get_unchecked(previous)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Option<Block>> + '_ {
+        (0..self.len).map(|i| unsafe { self.get_unchecked(i) })
+    }
+
+    fn palette_index_of(&mut self, value: Option<Block>) -> usize {
+        if let Some(index) = self.palette.iter().position(|b| *b == value) {
+            return index;
+        }
+
+        if self.palette.len() == 1 << self.bits {
+            self.compact();
+        }
+        self.palette.push(value);
+        if self.palette.len() > 1 << self.bits {
+            self.grow();
+        }
+        self.palette.len() - 1
+    }
+
+    /// Drops the palette entries no cell points to and renumbers the indices of the others.
+    fn compact(&mut self) {
+        let mut is_used = vec![false; self.palette.len()];
+        for i in 0..self.len {
+            is_used[self.get_index(i)] = true;
+        }
+        if is_used.iter().all(|&used| used) {
+            return;
+        }
+
+        let mut new_indices = vec![0; self.palette.len()];
+        let mut palette = Vec::with_capacity(self.palette.len());
+        for (index, value) in self.palette.iter().enumerate() {
+            if is_used[index] {
+                new_indices[index] = palette.len();
+                palette.push(*value);
+            }
+        }
+        for i in 0..self.len {
+            self.set_index(i, new_indices[self.get_index(i)]);
+        }
+        self.palette = palette;
+    }
+
+    fn grow(&mut self) {
+        let new_bits = self.bits + 1;
+        assert!(new_bits <= MAX_BITS, "Palette can't hold more than {} entries", 1 << MAX_BITS);
+
+        let mut grown = PaletteStorage {
+            palette: Vec::new(),
+            data: vec![0; Self::words_for(self.len, new_bits)],
+            bits: new_bits,
+            len: self.len,
+        };
+        for i in 0..self.len {
+            grown.set_index(i, self.get_index(i));
+        }
+
+        self.data = grown.data;
+        self.bits = new_bits;
+    }
+
+    fn get_index(&self, i: usize) -> usize {
+        let per_word = WORD_BITS / self.bits;
+        let word = self.data[i / per_word];
+        let shift = (i % per_word) * self.bits;
+        ((word >> shift) & self.mask()) as usize
+    }
+
+    fn set_index(&mut self, i: usize, index: usize) {
+        let per_word = WORD_BITS / self.bits;
+        let shift = (i % per_word) * self.bits;
+        let mask = self.mask();
+        let word = &mut self.data[i / per_word];
+        *word = (*word & !(mask << shift)) | ((index as u64 & mask) << shift);
+    }
+
+    fn mask(&self) -> u64 {
+        (1u64 << self.bits) - 1
+    }
+
+    fn words_for(len: usize, bits: usize) -> usize {
+        len.div_ceil(WORD_BITS / bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::chunk::{BlockState, BlockType};
+
+    fn block(state: u16) -> Option<Block> {
+        Some(Block { block_type: &BlockType::STONE, state: BlockState(state) })
+    }
+
+    #[test]
+    fn values_survive_growth() {
+        let len = 4096;
+        let mut storage = PaletteStorage::new(len);
+        for i in 0..len {
+            let previous = unsafe { storage.set_unchecked(i, block(i as u16 % 300)) };
+            assert!(previous.is_none());
+        }
+        assert_eq!(storage.bits(), 9);
+        for i in 0..len {
+            assert!(unsafe { *storage.get_unchecked(i) } == block(i as u16 % 300));
+        }
+
+        let rebuilt = PaletteStorage::from_parts(storage.palette().to_vec(), storage.bits(), storage.data().to_vec(), len).unwrap();
+        assert!(rebuilt.iter().eq(storage.iter()));
+    }
+
+    #[test]
+    fn overwritten_values_are_reused() {
+        let mut storage = PaletteStorage::new(64);
+        for value in 0..64 * 1500u32 {
+            unsafe {
+                storage.set_unchecked(value as usize % 64, block(value as u16));
+            }
+        }
+        assert!(storage.palette().len() <= 65);
+        assert!(storage.bits() <= 7);
+        for i in 0..64 {
+            assert!(unsafe { *storage.get_unchecked(i) } == block((64 * 1499 + i as u32) as u16));
+        }
+    }
+}
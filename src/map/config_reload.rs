@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use bevy::prelude::*;
+use crate::map::chunk::Chunk;
+use crate::map::generator::{ActiveWorldGenerator, NoiseGenerator, WorldGenConfig, WorldGenConfigError};
+use crate::map::GeneratingColumns;
+use crate::utils::ChunkPos;
+
+/// How often the config file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Rebuilds the noise generator whenever its config file changes on disk and despawns
+/// the columns nobody modified, so they are generated again around the camera.
+pub struct ConfigReloadPlugin {
+    pub path: PathBuf,
+    /// Seed used when the file names none.
+    pub seed: u32,
+}
+
+impl Plugin for ConfigReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(WatchedConfig {
+                path: self.path.clone(),
+                seed: self.seed,
+                modified: get_modified_time(&self.path),
+                timer: Timer::new(POLL_INTERVAL, TimerMode::Repeating),
+            })
+            .add_systems(PreUpdate, reload_config);
+    }
+}
+
+#[derive(Resource)]
+struct WatchedConfig {
+    path: PathBuf,
+    seed: u32,
+    /// Last modification of the file seen, `None` while it doesn't exist.
+    modified: Option<SystemTime>,
+    timer: Timer,
+}
+
+/// Noise generator tuned by the config file, with the seed it names or `seed`.
+pub fn load_noise_generator(path: &Path, seed: u32) -> Result<NoiseGenerator, WorldGenConfigError> {
+    let config = WorldGenConfig::load(path)?;
+    Ok(NoiseGenerator { seed: config.seed.unwrap_or(seed), config })
+}
+
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn reload_config(time: Res<Time>,
+                 mut watched: ResMut<WatchedConfig>,
+                 mut generator: ResMut<ActiveWorldGenerator>,
+                 mut generating: ResMut<GeneratingColumns>,
+                 chunks: Query<(Entity, &ChunkPos, &Chunk)>,
+                 mut commands: Commands,) {
+    if !watched.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = get_modified_time(&watched.path);
+    if modified.is_none() || modified == watched.modified {
+        return;
+    }
+    watched.modified = modified;
+
+    let new_generator = match load_noise_generator(&watched.path, watched.seed) {
+        Ok(new_generator) => new_generator,
+        Err(err) => {
+            warn!("Failed to reload world generation config from {:?}, keeping the current one: {err:?}", watched.path);
+            return;
+        }
+    };
+    if generator.0.get_config() == Some(&new_generator.config) {
+        return;
+    }
+
+    info!("World generation config {:?} changed, regenerating unmodified chunks", watched.path);
+    commands.insert_resource(new_generator.config.clone());
+    generator.0 = Arc::new(new_generator);
+    // Columns being generated are made with the old config.
+    generating.0.clear();
+
+    // Columns are saved and generated as a whole, a single modified chunk keeps its column.
+    let modified_columns: HashSet<_> = chunks.iter()
+        .filter(|(_, _, chunk)| chunk.is_modified)
+        .map(|(_, pos, _)| (pos.0.x, pos.0.z))
+        .collect();
+    for (entity, pos, _) in &chunks {
+        if !modified_columns.contains(&(pos.0.x, pos.0.z)) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use crate::map::generator::{ActiveWorldGenerator, WorldGenerator, CUBE_SIDE};
+use crate::map::voxel_world::ChunkIndex;
+use crate::utils::{ChunkPos, CHUNK_SIZE, CHUNK_SIZE_F32, WORLD_Y_OFFSET};
+
+/// Far terrain tiles are this many chunk columns wide.
+const TILE_COLUMNS: i32 = 8;
+/// Surface samples along a chunk column.
+const COLUMN_SAMPLES: usize = 2;
+/// Surface samples along a tile, the last ones are shared with the next tiles.
+const TILE_SAMPLES: usize = TILE_COLUMNS as usize * COLUMN_SAMPLES + 1;
+/// Tiles drawn on each side of the tile with the camera.
+const FAR_TERRAIN_TILES: i32 = 8;
+const MAX_GENERATING_TILES: usize = 4;
+/// Direction the slopes of the far terrain are shaded from.
+const SUN_DIRECTION: Vec3 = Vec3::new(0.3, 1.0, 0.5);
+/// Brightness of slopes facing away from the sun.
+const MIN_BRIGHTNESS: f32 = 0.5;
+
+/// How far the far terrain reaches from the camera, cameras should see at least this far.
+pub const FAR_TERRAIN_DISTANCE: f32 = (FAR_TERRAIN_TILES + 1) as f32 * TILE_COLUMNS as f32 * CHUNK_SIZE_F32 * CUBE_SIDE;
+
+/// Draws a low-poly surface of the terrain beyond the loaded chunks, built from the
+/// height and biome noise alone. Columns are cut out of it as their chunks load.
+pub struct FarTerrainPlugin;
+
+impl Plugin for FarTerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<FarTerrain>()
+            .add_systems(Update, (reset_far_terrain, spawn_far_tiles, collect_far_tiles, despawn_far_tiles, cut_out_loaded_columns).chain());
+    }
+}
+
+/// Spawned far terrain tiles and the ones being generated on the [`AsyncComputeTaskPool`].
+#[derive(Resource, Default)]
+struct FarTerrain {
+    tiles: HashMap<(i32, i32), Entity>,
+    generating: HashMap<(i32, i32), Task<Option<TileSurface>>>,
+    /// Set once the generator turns out not to tell the surface without generating the columns.
+    is_disabled: bool,
+}
+
+/// Height and color of the surface at every sample of a tile, row by row.
+struct TileSurface(Vec<(f32, Color)>);
+
+#[derive(Component)]
+struct FarTerrainTile {
+    pos: (i32, i32),
+    surface: TileSurface,
+    /// Columns with loaded chunks, bit `z * TILE_COLUMNS + x`.
+    loaded_columns: u64,
+}
+
+fn get_camera_tile(cameras: &Query<&Transform, With<Camera>>) -> Option<(i32, i32)> {
+    let camera = cameras.iter().next()?;
+    let tile_side = TILE_COLUMNS as f32 * CHUNK_SIZE_F32 * CUBE_SIDE;
+    Some(((camera.translation.x / tile_side).floor() as i32, (camera.translation.z / tile_side).floor() as i32))
+}
+
+fn is_tile_visible((x, z): (i32, i32), (camera_x, camera_z): (i32, i32)) -> bool {
+    (x - camera_x).abs() <= FAR_TERRAIN_TILES && (z - camera_z).abs() <= FAR_TERRAIN_TILES
+}
+
+/// Drops every tile when the world generator is replaced, they are generated again with the new one.
+fn reset_far_terrain(generator: Res<ActiveWorldGenerator>,
+                     mut far_terrain: ResMut<FarTerrain>,
+                     mut commands: Commands,) {
+    if !generator.is_changed() || generator.is_added() {
+        return;
+    }
+
+    for (_, entity) in far_terrain.tiles.drain() {
+        commands.entity(entity).despawn();
+    }
+    far_terrain.generating.clear();
+    far_terrain.is_disabled = false;
+}
+
+fn spawn_far_tiles(cameras: Query<&Transform, With<Camera>>,
+                   generator: Res<ActiveWorldGenerator>,
+                   mut far_terrain: ResMut<FarTerrain>,) {
+    let Some(camera_tile) = get_camera_tile(&cameras) else {
+        return;
+    };
+    if far_terrain.is_disabled {
+        return;
+    }
+
+    // Dropping a task cancels it if it hasn't started yet.
+    far_terrain.generating.retain(|&tile, _| is_tile_visible(tile, camera_tile));
+
+    let free_slots = MAX_GENERATING_TILES.saturating_sub(far_terrain.generating.len());
+    if free_slots == 0 {
+        return;
+    }
+
+    let (camera_x, camera_z) = camera_tile;
+    let mut new_tiles: Vec<_> = (camera_x - FAR_TERRAIN_TILES..=camera_x + FAR_TERRAIN_TILES)
+        .flat_map(|x| (camera_z - FAR_TERRAIN_TILES..=camera_z + FAR_TERRAIN_TILES).map(move |z| (x, z)))
+        .filter(|tile| !far_terrain.tiles.contains_key(tile) && !far_terrain.generating.contains_key(tile))
+        .collect();
+    new_tiles.sort_by_key(|&(x, z)| (x - camera_x).abs() + (z - camera_z).abs());
+
+    let pool = AsyncComputeTaskPool::get();
+    for tile in new_tiles.into_iter().take(free_slots) {
+        let generator = generator.0.clone();
+        let task = pool.spawn(async move {
+            get_tile_surface(tile, generator.as_ref())
+        });
+        far_terrain.generating.insert(tile, task);
+    }
+}
+
+/// `None` if the generator can't tell the surface without generating the columns.
+fn get_tile_surface((tile_x, tile_z): (i32, i32), generator: &dyn WorldGenerator) -> Option<TileSurface> {
+    let mut surface = vec![(0.0, Color::BLACK); TILE_SAMPLES * TILE_SAMPLES];
+    for column_z in 0..=TILE_COLUMNS {
+        for column_x in 0..=TILE_COLUMNS {
+            let column = generator.get_column_surface(tile_x * TILE_COLUMNS + column_x, tile_z * TILE_COLUMNS + column_z, COLUMN_SAMPLES)?;
+            for (i, (height, color)) in column.into_iter().enumerate() {
+                let x = column_x as usize * COLUMN_SAMPLES + i % COLUMN_SAMPLES;
+                let z = column_z as usize * COLUMN_SAMPLES + i / COLUMN_SAMPLES;
+                if x < TILE_SAMPLES && z < TILE_SAMPLES {
+                    // The surface is on top of the highest block.
+                    let y = (height as f32 - WORLD_Y_OFFSET - 0.5) * CUBE_SIDE;
+                    surface[z * TILE_SAMPLES + x] = (y, color);
+                }
+            }
+        }
+    }
+    Some(TileSurface(surface))
+}
+
+fn collect_far_tiles(index: Res<ChunkIndex>,
+                     mut far_terrain: ResMut<FarTerrain>,
+                     mut commands: Commands,
+                     mut materials: ResMut<Assets<StandardMaterial>>,
+                     mut meshes: ResMut<Assets<Mesh>>,) {
+    let finished: Vec<_> = far_terrain.generating.iter()
+        .filter(|(_, task)| task.is_finished())
+        .map(|(&tile, _)| tile)
+        .collect();
+
+    for tile in finished {
+        let task = far_terrain.generating.remove(&tile).unwrap();
+        let Some(surface) = block_on(task) else {
+            info!("World generator has no surface for the far terrain, disabling it");
+            far_terrain.is_disabled = true;
+            far_terrain.generating.clear();
+            return;
+        };
+        let loaded_columns = get_loaded_columns(tile, &index);
+        let mesh = create_tile_mesh(&surface, loaded_columns);
+        let tile_side = TILE_COLUMNS as f32 * CHUNK_SIZE_F32 * CUBE_SIDE;
+        let entity = commands
+            .spawn((
+                FarTerrainTile { pos: tile, surface, loaded_columns },
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    unlit: true,
+                    ..default()
+                })),
+                Transform::from_xyz(tile.0 as f32 * tile_side, 0.0, tile.1 as f32 * tile_side),
+                Visibility::Visible,
+            ))
+            .id();
+        far_terrain.tiles.insert(tile, entity);
+    }
+}
+
+fn despawn_far_tiles(cameras: Query<&Transform, With<Camera>>,
+                     mut far_terrain: ResMut<FarTerrain>,
+                     mut commands: Commands,) {
+    let Some(camera_tile) = get_camera_tile(&cameras) else {
+        return;
+    };
+
+    far_terrain.tiles.retain(|&tile, &mut entity| {
+        let is_visible = is_tile_visible(tile, camera_tile);
+        if !is_visible {
+            commands.entity(entity).despawn();
+        }
+        is_visible
+    });
+}
+
+/// Rebuilds the tiles whose columns have been loaded or unloaded, so the far terrain never covers chunks.
+fn cut_out_loaded_columns(index: Res<ChunkIndex>,
+                          mut tiles: Query<(&mut FarTerrainTile, &Mesh3d)>,
+                          mut meshes: ResMut<Assets<Mesh>>,) {
+    if !index.is_changed() {
+        return;
+    }
+
+    for (mut tile, mesh) in &mut tiles {
+        let loaded_columns = get_loaded_columns(tile.pos, &index);
+        if loaded_columns == tile.loaded_columns {
+            continue;
+        }
+        tile.loaded_columns = loaded_columns;
+        meshes.insert(mesh.id(), create_tile_mesh(&tile.surface, loaded_columns));
+    }
+}
+
+fn get_loaded_columns((tile_x, tile_z): (i32, i32), index: &ChunkIndex) -> u64 {
+    let mut loaded_columns = 0;
+    for z in 0..TILE_COLUMNS {
+        for x in 0..TILE_COLUMNS {
+            if index.contains(&ChunkPos::new(tile_x * TILE_COLUMNS + x, 0, tile_z * TILE_COLUMNS + z)) {
+                loaded_columns |= 1 << (z * TILE_COLUMNS + x);
+            }
+        }
+    }
+    loaded_columns
+}
+
+/// One quad between every four neighbouring samples, except over the loaded columns.
+/// Quads are shaded by their slope as the far terrain has no light.
+fn create_tile_mesh(surface: &TileSurface, loaded_columns: u64) -> Mesh {
+    let step = (CHUNK_SIZE / COLUMN_SAMPLES) as f32 * CUBE_SIDE;
+    let get_vertex = |x: usize, z: usize| {
+        let (y, color) = surface.0[z * TILE_SAMPLES + x];
+        // Samples are at block centres, blocks start half a block before.
+        (Vec3::new(x as f32 * step - 0.5 * CUBE_SIDE, y, z as f32 * step - 0.5 * CUBE_SIDE), color.to_srgba())
+    };
+
+    let mut vertices: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    for z in 0..TILE_SAMPLES - 1 {
+        for x in 0..TILE_SAMPLES - 1 {
+            let column = (z / COLUMN_SAMPLES) as i32 * TILE_COLUMNS + (x / COLUMN_SAMPLES) as i32;
+            if loaded_columns & (1 << column) != 0 {
+                continue;
+            }
+
+            let corners = [get_vertex(x, z), get_vertex(x, z + 1), get_vertex(x + 1, z + 1), get_vertex(x + 1, z)];
+            let normal = (corners[1].0 - corners[3].0).cross(corners[2].0 - corners[0].0).normalize();
+            let brightness = MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * normal.dot(SUN_DIRECTION.normalize()).max(0.0);
+
+            let first = vertices.len() as u32;
+            for (position, color) in corners {
+                vertices.push(position.to_array());
+                normals.push(normal.to_array());
+                colors.push([color.red * brightness, color.green * brightness, color.blue * brightness, 1.0]);
+            }
+            indices.extend([0, 1, 2, 0, 2, 3].map(|i| i + first));
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, VertexAttributeValues::Float32x3(vertices));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::Float32x3(normals));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, VertexAttributeValues::Float32x4(colors));
+    mesh.insert_indices(Indices::U32(indices));
+    mesh
+}
@@ -1,4 +1,5 @@
 use bevy::prelude::Color;
+use super::WorldGenConfig;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum Biome {
@@ -12,27 +13,36 @@ pub enum Biome {
     Ocean,
 }
 
-pub const OCEAN_HEIGHT: u8 = 127 - 25;
-pub const MOUNTAIN_HEIGHT: u8 = 127 + 70;
-pub const ICE_PIKE_HEIGHT: u8 = 127 + 80;
-pub const MAX_DESERT_HEIGHT: u8 = 127 + 60;
-
-pub const FREEZE_TEMP: u8 = 50;
-pub const FOREST_TEMP: u8 = 100;
-pub const PLAINS_TEMP: u8 = 127;
-
 impl Biome {
-    pub fn from_map(temperature: u8, vertical: u8) -> Biome {
-        match (temperature, vertical) {
-            (0..FREEZE_TEMP,           0..OCEAN_HEIGHT)                  => Biome::FrozenOcean,
-            (FREEZE_TEMP..=u8::MAX,     0..OCEAN_HEIGHT)                  => Biome::Ocean,
-            (0..FREEZE_TEMP,           _)                                => Biome::Tundra,
-            (_,                        MOUNTAIN_HEIGHT..ICE_PIKE_HEIGHT) => Biome::Mountain,
-            (_,                        ICE_PIKE_HEIGHT..=u8::MAX)         => Biome::IcePike,
-            (FREEZE_TEMP..FOREST_TEMP, _)                                => Biome::Forest,
-            (FOREST_TEMP..PLAINS_TEMP, _)                                => Biome::Plains,
-            (PLAINS_TEMP..=u8::MAX,     OCEAN_HEIGHT..MAX_DESERT_HEIGHT)  => Biome::Desert,
-            _                                                            => Biome::Forest,
+    pub const ALL: [Biome; 8] = [
+        Biome::Tundra,
+        Biome::Plains,
+        Biome::Forest,
+        Biome::Desert,
+        Biome::Mountain,
+        Biome::IcePike,
+        Biome::FrozenOcean,
+        Biome::Ocean,
+    ];
+
+    pub fn from_map(temperature: u8, vertical: u8, config: &WorldGenConfig) -> Biome {
+        let is_frozen = temperature < config.freeze_temperature;
+        if vertical < config.ocean_height {
+            if is_frozen { Biome::FrozenOcean } else { Biome::Ocean }
+        } else if is_frozen {
+            Biome::Tundra
+        } else if vertical >= config.ice_pike_height {
+            Biome::IcePike
+        } else if vertical >= config.mountain_height {
+            Biome::Mountain
+        } else if temperature < config.forest_temperature {
+            Biome::Forest
+        } else if temperature < config.plains_temperature {
+            Biome::Plains
+        } else if vertical < config.max_desert_height {
+            Biome::Desert
+        } else {
+            Biome::Forest
         }
     }
 }
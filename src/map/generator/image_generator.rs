@@ -0,0 +1,208 @@
+use std::path::Path;
+use bevy::asset::RenderAssetUsages;
+use bevy::color::ColorToPacked;
+use bevy::image::{CompressedImageFormats, Image, ImageSampler, ImageType};
+use bevy::log::info;
+use bevy::math::UVec2;
+use bevy::prelude::Color;
+use bevy::render::render_resource::TextureFormat;
+use crate::map::chunk::Chunk;
+use crate::utils::{CHUNK_SIZE, CHUNK_SIZE_I32};
+use super::biome::Biome;
+use super::noise_maps::*;
+use super::*;
+
+/// Height of white pixels, low enough for the toppings to stay under [`u8::MAX`].
+pub const MAX_IMAGE_HEIGHT: u8 = u8::MAX - MAX_TOPPING_HEIGHT;
+/// Height of black pixels and of the world around the images, just the unbreakable floor.
+const FLOOR_HEIGHT: u8 = 1;
+
+/// Terrain sketched in images. Every pixel of a grayscale heightmap is the height of a column,
+/// from the unbreakable floor for black to [`MAX_IMAGE_HEIGHT`] for white, and every pixel
+/// of the optional biome image is the [`Color`] of a [`Biome`].
+/// Toppings, caves and resources are added like in the noise terrain, tuned by `config`.
+///
+/// The images are centred on the origin, the world around them is sea floor.
+pub struct ImageGenerator {
+    pub seed: u32,
+    pub config: WorldGenConfig,
+    size: UVec2,
+    heights: Vec<u8>,
+    biomes: Option<Vec<Biome>>,
+}
+
+#[derive(Debug)]
+pub enum ImageGeneratorError {
+    Io(std::io::Error),
+    Decode(String),
+    /// The biome image isn't as large as the heightmap.
+    SizeMismatch(UVec2, UVec2),
+}
+
+impl ImageGenerator {
+    /// Loads the heightmap and the biome image. Without a biome image biomes come from
+    /// the temperature noise and the heights, like in the noise terrain.
+    pub fn load(heightmap: &Path, biome_image: Option<&Path>, seed: u32, config: WorldGenConfig) -> Result<Self, ImageGeneratorError> {
+        let (size, pixels) = load_pixels(heightmap)?;
+        let heights = pixels.iter().map(get_pixel_height).collect();
+
+        let biomes = match biome_image {
+            Some(path) => {
+                let (biome_size, pixels) = load_pixels(path)?;
+                if biome_size != size {
+                    return Err(ImageGeneratorError::SizeMismatch(size, biome_size));
+                }
+                Some(pixels.iter().map(get_closest_biome).collect())
+            }
+            None => None,
+        };
+
+        info!("Loaded {}x{} heightmap {heightmap:?}", size.x, size.y);
+        Ok(ImageGenerator { seed, config, size, heights, biomes })
+    }
+
+    /// Values of the pixels under the column, row by row, `outside` where the column leaves the image.
+    fn get_column_values<T: Copy>(&self, ch_x: i32, ch_z: i32, pixels: &[T], outside: T) -> ValueMap<T> {
+        let min_x = ch_x * CHUNK_SIZE_I32 + self.size.x as i32 / 2;
+        let min_z = ch_z * CHUNK_SIZE_I32 + self.size.y as i32 / 2;
+        let values = (0..CHUNK_SIZE_I32)
+            .flat_map(|z| (0..CHUNK_SIZE_I32).map(move |x| (min_x + x, min_z + z)))
+            .map(|(x, z)| {
+                let is_inside = (0..self.size.x as i32).contains(&x) && (0..self.size.y as i32).contains(&z);
+                if is_inside { pixels[(z as u32 * self.size.x + x as u32) as usize] } else { outside }
+            })
+            .collect();
+        ValueMap(values)
+    }
+
+    /// Biomes of the column from the biome image, or from the temperature noise sampled
+    /// at `samples` × `samples` points and the `base_heights` without one.
+    fn get_biome_map<'a>(&'a self, ch_x: i32, ch_z: i32, samples: usize,
+                         base_heights: &'a (dyn Noise2D<u8> + Sync)) -> Box<dyn Noise2D<Biome> + Sync + 'a> {
+        match &self.biomes {
+            Some(biomes) => Box::new(self.get_column_values(ch_x, ch_z, biomes, Biome::Ocean)),
+            None => Box::new(BiomeMap::new(ch_x, ch_z, self.seed.wrapping_sub(100), samples, base_heights, &self.config)),
+        }
+    }
+}
+
+impl WorldGenerator for ImageGenerator {
+    fn get_chunk_column(&self, ch_x: i32, ch_z: i32) -> Vec<Chunk> {
+        use std::time::Instant;
+        let now = Instant::now();
+        let base_heights = self.get_column_values(ch_x, ch_z, &self.heights, FLOOR_HEIGHT);
+        let biome_map = self.get_biome_map(ch_x, ch_z, CHUNK_SIZE, &base_heights);
+        let topping_map = ToppingMap::new(ch_x, ch_z, self.seed.wrapping_sub(200), CHUNK_SIZE, &base_heights, &*biome_map, &self.config);
+        let cave_map = CaveMap::new(ch_x, ch_z, self.seed, &*biome_map, &self.config);
+        let resource_map = ResourceMap::new(ch_x, ch_z, self.seed, &self.config);
+
+        let chunk_column = get_chunk_column(ch_x, ch_z, self.get_height(), &base_heights, &*biome_map, &topping_map, &cave_map, &resource_map);
+
+        let elapsed = now.elapsed();
+        info!("Chunk ({ch_x}, {ch_z}) generated from the heightmap. Total generation time: {elapsed:.2?}.");
+        chunk_column
+    }
+
+    fn get_column_surface(&self, ch_x: i32, ch_z: i32, samples: usize) -> Option<Vec<(u8, Color)>> {
+        let base_heights = self.get_column_values(ch_x, ch_z, &self.heights, FLOOR_HEIGHT);
+        let biome_map = self.get_biome_map(ch_x, ch_z, samples, &base_heights);
+        let topping_map = ToppingMap::new(ch_x, ch_z, self.seed.wrapping_sub(200), samples, &base_heights, &*biome_map, &self.config);
+        Some(get_column_surface(samples, &base_heights, &*biome_map, &topping_map))
+    }
+
+    fn get_config(&self) -> Option<&WorldGenConfig> {
+        Some(&self.config)
+    }
+}
+
+/// Size and RGBA pixels of the image, values are taken as they are stored.
+fn load_pixels(path: &Path) -> Result<(UVec2, Vec<[u8; 4]>), ImageGeneratorError> {
+    let bytes = std::fs::read(path).map_err(ImageGeneratorError::Io)?;
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("png");
+    let image = Image::from_buffer(&bytes,
+                                   ImageType::Extension(extension),
+                                   CompressedImageFormats::NONE,
+                                   true,
+                                   ImageSampler::Default,
+                                   RenderAssetUsages::MAIN_WORLD)
+        .map_err(|err| ImageGeneratorError::Decode(err.to_string()))?
+        .convert(TextureFormat::Rgba8UnormSrgb)
+        .ok_or_else(|| ImageGeneratorError::Decode("unsupported pixel format".to_string()))?;
+    let pixels = image.data.as_deref().unwrap_or_default()
+        .chunks_exact(4)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+        .collect();
+    Ok((image.size(), pixels))
+}
+
+/// Height of the column under a heightmap pixel, by its brightness.
+fn get_pixel_height([red, green, blue, _]: &[u8; 4]) -> u8 {
+    let brightness = (*red as u32 + *green as u32 + *blue as u32) / 3;
+    (FLOOR_HEIGHT as u32 + brightness * (MAX_IMAGE_HEIGHT - FLOOR_HEIGHT) as u32 / 255) as u8
+}
+
+/// Biome whose color is the closest to the pixel. Biomes of the same color can't be told apart,
+/// the first of them is taken.
+fn get_closest_biome(pixel: &[u8; 4]) -> Biome {
+    let distance = |biome: &Biome| {
+        let color = Color::from(*biome).to_srgba().to_u8_array();
+        (0..3).map(|i| (color[i] as i32 - pixel[i] as i32).pow(2)).sum::<i32>()
+    };
+    Biome::ALL.into_iter().min_by_key(distance).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::chunk::BlockType;
+    use crate::utils::BlockPos;
+    use super::*;
+
+    /// Generator of a single white pixel, above the block (0, 0) of the column (0, 0).
+    /// Its seed is the lowest, so seeds derived from it wrap around.
+    fn white_pixel() -> ImageGenerator {
+        ImageGenerator {
+            seed: 0,
+            config: WorldGenConfig::default(),
+            size: UVec2::new(1, 1),
+            heights: vec![get_pixel_height(&[255; 4])],
+            biomes: None,
+        }
+    }
+
+    fn get_top(column: &[Chunk], x: i32, z: i32) -> Option<usize> {
+        (0..MAP_HEIGHT).rev().find(|&y| column[y / CHUNK_SIZE]
+            .get_block_at(&BlockPos::new(x, (y % CHUNK_SIZE) as i32, z))
+            .unwrap()
+            .is_some())
+    }
+
+    #[test]
+    fn pixels_are_scaled_under_the_toppings() {
+        assert_eq!(get_pixel_height(&[0, 0, 0, 255]), FLOOR_HEIGHT);
+        assert_eq!(get_pixel_height(&[255; 4]), MAX_IMAGE_HEIGHT);
+
+        let generator = white_pixel();
+        let column = generator.get_chunk_column(0, 0);
+        let top = get_top(&column, 0, 0).unwrap();
+        assert!(top >= MAX_IMAGE_HEIGHT as usize - 1, "white pixel top at {top}");
+        assert!(generator.get_column_surface(0, 0, 4).is_some());
+    }
+
+    #[test]
+    fn biome_image_replaces_the_noise_biomes() {
+        let generator = ImageGenerator { biomes: Some(vec![Biome::Desert]), ..white_pixel() };
+        let surface = generator.get_column_surface(0, 0, 2).unwrap();
+        assert_eq!(surface[0].1, Color::from(Biome::Desert));
+        // Samples off the image are ocean.
+        assert!(surface[1..].iter().all(|(_, color)| *color == Color::from(Biome::Ocean)));
+    }
+
+    #[test]
+    fn sea_floor_around_the_image_is_unbreakable() {
+        let column = white_pixel().get_chunk_column(3, -2);
+        for (x, z) in [(0, 0), (17, 5), (31, 31)] {
+            let floor = column[0].get_block_at(&BlockPos::new(x, 0, z)).unwrap();
+            assert_eq!(floor.map(|block| block.block_type.name), Some(BlockType::UNBREAKABLE.name));
+        }
+    }
+}
@@ -1,124 +1,189 @@
 use rayon::iter::ParallelIterator;
 pub mod biome;
 mod noise_maps;
+mod world_generator;
+mod superflat;
+mod image_generator;
+mod world_gen_config;
 
-use bevy::prelude::{Resource, warn, info, Commands};
+use bevy::prelude::{Color, warn, info, Commands};
 use rayon::iter::IntoParallelIterator;
 use crate::map::generator::biome::*;
 use noise_maps::*;
+pub use world_generator::*;
+pub use superflat::*;
+pub use image_generator::*;
+pub use world_gen_config::*;
 use crate::map::chunk::{BlockType, Chunk};
 use crate::utils::{BlockPos, CHUNK_SIZE, CHUNK_SIZE_F64, CHUNK_SIZE_I32};
 
 pub const CUBE_SIDE: f32 = 1.0f32;
 
 const CHUNK_NOISE_BASE_BOUNDS: f64 = 10.0 / 256.0 * CHUNK_SIZE_F64;
-const MIN_ZOOM: f64 = 0.01 * 64.0 / CHUNK_SIZE_F64;
-const MAP_HEIGHT: usize = 256;
+pub const MAP_HEIGHT: usize = 256;
 
-#[derive(Resource)]
-pub struct Generator {
-    pub(crate) seed: u32,
+/// The default terrain: noise heights and biomes with caves and resources.
+#[derive(Clone)]
+pub struct NoiseGenerator {
+    pub seed: u32,
+    pub config: WorldGenConfig,
 }
 
-impl Generator {
-    pub fn get_chunk_column(&self, ch_x: i32, ch_z: i32) -> Vec<Chunk> {
+impl WorldGenerator for NoiseGenerator {
+    fn get_chunk_column(&self, ch_x: i32, ch_z: i32) -> Vec<Chunk> {
         use std::time::Instant;
         let now = Instant::now();
-        let base_heights = HeightMap::new(ch_x, ch_z, MIN_ZOOM, self.seed);
-        let biome_map = BiomeMap::new(ch_x, ch_z, MIN_ZOOM * 2.0, self.seed - 100, &base_heights);
-        let topping_map = ToppingMap::new(ch_x, ch_z, MIN_ZOOM, self.seed - 200, &base_heights, &biome_map);
-        let cave_map = CaveMap::new(ch_x, ch_z, MIN_ZOOM * 5.0, self.seed, &biome_map);
-        let resource_map = ResourceMap::new(ch_x, ch_z, MIN_ZOOM * 5.0, self.seed);
+        let config = &self.config;
+        let base_heights = HeightMap::new(ch_x, ch_z, config.min_zoom, self.seed, config.vertical_scale);
+        let biome_map = BiomeMap::new(ch_x, ch_z, self.seed.wrapping_sub(100), CHUNK_SIZE, &base_heights, config);
+        let topping_map = ToppingMap::new(ch_x, ch_z, self.seed.wrapping_sub(200), CHUNK_SIZE, &base_heights, &biome_map, config);
+        let cave_map = CaveMap::new(ch_x, ch_z, self.seed, &biome_map, config);
+        let resource_map = ResourceMap::new(ch_x, ch_z, self.seed, config);
         let noise_elapsed = now.elapsed();
 
-        let chunk_column = (0..(MAP_HEIGHT / CHUNK_SIZE))
-            .into_par_iter()
-            .map(|ch_y| self.get_chunk(ch_x, ch_y as i32, ch_z, &base_heights, &biome_map, &topping_map, &cave_map, &resource_map))
-            .collect();
+        let chunk_column = get_chunk_column(ch_x, ch_z, self.get_height(), &base_heights, &biome_map, &topping_map, &cave_map, &resource_map);
 
         let elapsed = now.elapsed();
         info!("Chunk ({ch_x}, {ch_z}) generated. Noise generation took {noise_elapsed:.2?}. Total generation time: {elapsed:.2?}.");
         chunk_column
     }
 
-    fn get_chunk(&self,
-                 ch_x: i32, ch_y: i32, ch_z: i32,
-                 base_heights: &HeightMap,
-                 biome_map: &BiomeMap,
-                 topping_map: &ToppingMap,
-                 cave_map: &CaveMap,
-                 resource_map: &ResourceMap) -> Chunk {
-        use std::time::Instant;
-        let now = Instant::now();
+    fn get_column_surface(&self, ch_x: i32, ch_z: i32, samples: usize) -> Option<Vec<(u8, Color)>> {
+        let config = &self.config;
+        let base_heights = HeightMap::with_samples(ch_x, ch_z, config.min_zoom, self.seed, samples, config.vertical_scale);
+        let biome_map = BiomeMap::new(ch_x, ch_z, self.seed.wrapping_sub(100), samples, &base_heights, config);
+        let topping_map = ToppingMap::new(ch_x, ch_z, self.seed.wrapping_sub(200), samples, &base_heights, &biome_map, config);
+        Some(get_column_surface(samples, &base_heights, &biome_map, &topping_map))
+    }
 
-        let mut chunk = Chunk::new();
-        let mut i = 0;
-        for z in 0..CHUNK_SIZE_I32 {
-            for x in 0..CHUNK_SIZE_I32 {
-                let min_y = ch_y * CHUNK_SIZE_I32;
-                let map_height = base_heights.get(x, z) as i32;
-                let mut height_in_chunk = i32::min(map_height, min_y + CHUNK_SIZE_I32) - min_y;
-                let biome = biome_map.get(x, z);
-                let ty = topping_map.get(x, z);
-                let topping_height = topping_map.get(x, z) as i32;
-                let topping_height_in_chunk = i32::min(topping_height, min_y + CHUNK_SIZE_I32) - min_y;
-
-                for y in 0..height_in_chunk {
-                    let block_pos = BlockPos::new(x, y, z);
-                    if cave_map.get(x, y + min_y, z) {
-                        continue;
-                    }
-                    unsafe {
-                        if (y == 0 && ch_y == 0) {
-                            chunk.spawn_block_unchecked(&block_pos, &BlockType::UNBREAKABLE);
-                        } else {
-                            chunk.spawn_block_unchecked(&block_pos, resource_map.get(x, y + min_y, z));
-                        }
-                    }
-                    i += 1;
-                }
+    fn get_config(&self) -> Option<&WorldGenConfig> {
+        Some(&self.config)
+    }
+}
 
-                if (topping_height_in_chunk < 0) {
+/// Chunks of a column `height` blocks high, generated in parallel.
+fn get_chunk_column(ch_x: i32, ch_z: i32,
+                    height: usize,
+                    base_heights: &(dyn Noise2D<u8> + Sync),
+                    biome_map: &(dyn Noise2D<Biome> + Sync),
+                    topping_map: &(dyn Noise2D<u8> + Sync),
+                    cave_map: &CaveMap,
+                    resource_map: &ResourceMap) -> Vec<Chunk> {
+    (0..(height / CHUNK_SIZE))
+        .into_par_iter()
+        .map(|ch_y| get_chunk(ch_x, ch_y as i32, ch_z, base_heights, biome_map, topping_map, cave_map, resource_map))
+        .collect()
+}
+
+/// Height of the surface and color of the biome at `samples` × `samples` points spread evenly over the column.
+fn get_column_surface(samples: usize,
+                      base_heights: &dyn Noise2D<u8>,
+                      biome_map: &dyn Noise2D<Biome>,
+                      topping_map: &dyn Noise2D<u8>) -> Vec<(u8, Color)> {
+    let step = (CHUNK_SIZE / samples) as i32;
+    (0..samples as i32)
+        .flat_map(|z| (0..samples as i32).map(move |x| (x * step, z * step)))
+        .map(|(x, z)| (topping_map.get(x, z).max(base_heights.get(x, z)), Color::from(biome_map.get(x, z))))
+        .collect()
+}
+
+/// Fills the chunk with the terrain described by the maps: ground up to the base height
+/// with resources and caves carved out, covered by the biome topping.
+fn get_chunk(ch_x: i32, ch_y: i32, ch_z: i32,
+             base_heights: &dyn Noise2D<u8>,
+             biome_map: &dyn Noise2D<Biome>,
+             topping_map: &dyn Noise2D<u8>,
+             cave_map: &CaveMap,
+             resource_map: &ResourceMap) -> Chunk {
+    use std::time::Instant;
+    let now = Instant::now();
+
+    let mut chunk = Chunk::new();
+    let mut i = 0;
+    for z in 0..CHUNK_SIZE_I32 {
+        for x in 0..CHUNK_SIZE_I32 {
+            let min_y = ch_y * CHUNK_SIZE_I32;
+            let map_height = base_heights.get(x, z) as i32;
+            let mut height_in_chunk = i32::min(map_height, min_y + CHUNK_SIZE_I32) - min_y;
+            let biome = biome_map.get(x, z);
+            let ty = topping_map.get(x, z);
+            let topping_height = topping_map.get(x, z) as i32;
+            let topping_height_in_chunk = i32::min(topping_height, min_y + CHUNK_SIZE_I32) - min_y;
+
+            for y in 0..height_in_chunk {
+                let block_pos = BlockPos::new(x, y, z);
+                if cave_map.get(x, y + min_y, z) {
                     continue;
                 }
-
-                if (height_in_chunk < 0) {
-                    height_in_chunk = 0;
+                unsafe {
+                    if (y == 0 && ch_y == 0) {
+                        chunk.spawn_block_unchecked(&block_pos, &BlockType::UNBREAKABLE);
+                    } else {
+                        chunk.spawn_block_unchecked(&block_pos, resource_map.get(x, y + min_y, z));
+                    }
                 }
+                i += 1;
+            }
 
-                for y in height_in_chunk..topping_height_in_chunk {
-                    let block_pos = BlockPos::new(x, y, z);
-                    if  biome != Biome::Ocean
-                        && biome != Biome::FrozenOcean
-                        && cave_map.get(x, y + min_y, z) {
-                        continue;
-                    }
+            if (topping_height_in_chunk < 0) {
+                continue;
+            }
 
-                    let mut block = match biome {
-                        Biome::Tundra => &BlockType::ICE,
-                        Biome::Plains => &BlockType::DIRT,
-                        Biome::Forest => &BlockType::FOREST_DIRT,
-                        Biome::Desert => &BlockType::SAND,
-                        Biome::Mountain => &BlockType::STONE,
-                        Biome::IcePike => &BlockType::ICE,
-                        Biome::FrozenOcean => &BlockType::ICE,
-                        Biome::Ocean => &BlockType::WATER,
-                    };
-
-                    if block == &BlockType::ICE && biome == Biome::FrozenOcean && y + min_y < ty as i32 - 2 {
-                        block = &BlockType::WATER;
-                    }
+            if (height_in_chunk < 0) {
+                height_in_chunk = 0;
+            }
 
-                    unsafe {
-                        chunk.spawn_block_unchecked(&block_pos, block);
-                    }
-                    i += 1;
+            // Caves must not drain columns that hold fluids.
+            let is_fluid_column = get_topping_block(biome, true).is_fluid;
+            for y in height_in_chunk..topping_height_in_chunk {
+                let block_pos = BlockPos::new(x, y, z);
+                if !is_fluid_column && cave_map.get(x, y + min_y, z) {
+                    continue;
                 }
+
+                let is_deep = y + min_y < ty as i32 - 2;
+                let block = get_topping_block(biome, is_deep);
+
+                unsafe {
+                    chunk.spawn_block_unchecked(&block_pos, block);
+                }
+                i += 1;
             }
         }
+    }
 
-        let elapsed = now.elapsed();
-        info!("Chunk ({ch_x}, {ch_y}, {ch_z}) generated: {i} cubes. Total generation time: {elapsed:.2?}.");
-        chunk
+    let elapsed = now.elapsed();
+    info!("Chunk ({ch_x}, {ch_y}, {ch_z}) generated: {i} cubes. Total generation time: {elapsed:.2?}.");
+    chunk
+}
+
+fn get_topping_block(biome: Biome, is_deep: bool) -> &'static BlockType {
+    match biome {
+        Biome::Tundra => &BlockType::ICE,
+        Biome::Plains => &BlockType::DIRT,
+        Biome::Forest => &BlockType::FOREST_DIRT,
+        Biome::Desert => &BlockType::SAND,
+        Biome::Mountain => &BlockType::STONE,
+        Biome::IcePike => &BlockType::ICE,
+        Biome::FrozenOcean if is_deep => &BlockType::WATER,
+        Biome::FrozenOcean => &BlockType::ICE,
+        Biome::Ocean => &BlockType::WATER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_seed_generates_a_column() {
+        for seed in [0, 1, u32::MAX] {
+            let config = WorldGenConfig::load_str(&format!("(seed: {seed})")).unwrap();
+            let generator = NoiseGenerator { seed: config.seed.unwrap(), config };
+            let column = generator.get_chunk_column(-3, 5);
+            assert_eq!(column.len(), generator.get_height() / CHUNK_SIZE);
+            assert!(column[0].get_amount_of_blocks() > 0, "seed {seed} generates an empty column");
+            assert!(generator.get_column_surface(-3, 5, 4).is_some());
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,24 +1,28 @@
 use crate::map::generator::biome::Biome;
-use crate::map::generator::noise_maps::utils::Noise2D;
+use crate::map::generator::noise_maps::utils::{Noise2D, NOISE_SCALE};
+use crate::map::generator::WorldGenConfig;
 use super::HeightMap;
 
 pub struct BiomeMap<'a> {
     temperature: HeightMap,
-    height_map: &'a HeightMap,
+    height_map: &'a (dyn Noise2D<u8> + Sync),
+    config: &'a WorldGenConfig,
 }
 
 impl BiomeMap<'_> {
-    pub fn new(x: i32, z: i32, zoom: f64, seed: u32, height_map: &HeightMap) -> BiomeMap
+    pub fn new<'a>(x: i32, z: i32, seed: u32, samples: usize,
+                   height_map: &'a (dyn Noise2D<u8> + Sync), config: &'a WorldGenConfig) -> BiomeMap<'a>
     {
         BiomeMap {
-            temperature: HeightMap::new(x, z, zoom, seed),
+            temperature: HeightMap::with_samples(x, z, config.min_zoom * config.biome_zoom, seed, samples, NOISE_SCALE),
             height_map,
+            config,
         }
     }
 }
 
 impl Noise2D<Biome> for BiomeMap<'_> {
     fn get(&self, x: i32, z: i32) -> Biome {
-        Biome::from_map(self.temperature.get(x, z), self.height_map.get(x, z))
+        Biome::from_map(self.temperature.get(x, z), self.height_map.get(x, z), self.config)
     }
 }
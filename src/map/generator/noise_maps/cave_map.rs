@@ -1,28 +1,30 @@
 use noise::core::perlin::perlin_3d;
 use noise::permutationtable::PermutationTable;
 use crate::map::generator::biome::Biome;
-use super::{BiomeMap, Noise2D, Noise3D};
-
-const CAVE_PROBABILITY: f64 = 0.3;
+use crate::map::generator::WorldGenConfig;
+use super::{Noise2D, Noise3D};
 
 pub struct CaveMap<'a> {
     perm_table1: PermutationTable,
     perm_table2: PermutationTable,
-    biome_map: &'a BiomeMap<'a>,
+    biome_map: &'a (dyn Noise2D<Biome> + Sync),
     ch_x: f64,
     ch_z: f64,
     zoom: f64,
+    probability: f64,
 }
 
 impl CaveMap<'_> {
-    pub fn new<'a> (x: i32, z: i32, zoom: f64, seed: u32, biome_map: &'a BiomeMap<'a>) -> CaveMap<'a> {
+    pub fn new<'a> (x: i32, z: i32, seed: u32, biome_map: &'a (dyn Noise2D<Biome> + Sync), config: &WorldGenConfig) -> CaveMap<'a> {
+        let zoom = config.min_zoom * config.cave_zoom;
         CaveMap {
             perm_table1: PermutationTable::new(seed),
-            perm_table2: PermutationTable::new(seed - 758),
+            perm_table2: PermutationTable::new(seed.wrapping_sub(758)),
             biome_map,
             ch_x: x as f64 * zoom,
             ch_z: z as f64 * zoom,
             zoom,
+            probability: config.cave_probability,
         }
     }
 }
@@ -38,7 +40,7 @@ impl Noise3D<bool> for CaveMap<'_> {
         let res2 = perlin_3d([fx, fy, fz], &self.perm_table2);
         let value = (res1 + res2 + 2.0) / 4.0;
 
-        return value < CAVE_PROBABILITY
+        return value < self.probability
     }
 
     fn get_zoom(&self) -> f64 {
@@ -6,26 +6,40 @@ use super::utils::*;
 
 pub struct HeightMap {
     noise: NoiseMap,
+    /// Blocks between two noise samples.
+    step: usize,
+    /// Values go from half of it to one and a half of it.
+    scale: f32,
 }
 
 impl HeightMap {
-    pub fn new(x: i32, z: i32, zoom: f64, seed: u32) -> Self
+    pub fn new(x: i32, z: i32, zoom: f64, seed: u32, scale: f32) -> Self
+    {
+        Self::with_samples(x, z, zoom, seed, CHUNK_SIZE, scale)
+    }
+
+    /// Map with only `samples` × `samples` noise values spread evenly over the chunk,
+    /// each of them shared by the blocks up to the next one.
+    pub fn with_samples(x: i32, z: i32, zoom: f64, seed: u32, samples: usize, scale: f32) -> Self
     {
         HeightMap {
-            noise: Self::generate_2d_noise(x, z, zoom, seed)
+            noise: Self::generate_2d_noise(x, z, zoom, seed, samples),
+            step: CHUNK_SIZE / samples,
+            scale,
         }
     }
 
-    fn generate_2d_noise(x: i32, y: i32, zoom: f64, seed: u32) -> NoiseMap {
+    fn generate_2d_noise(x: i32, y: i32, zoom: f64, seed: u32, samples: usize) -> NoiseMap {
         let start_x = (x as f64) * CHUNK_NOISE_BASE_BOUNDS * zoom;
         let start_y = (y as f64) * CHUNK_NOISE_BASE_BOUNDS * zoom;
         let end_x = (x as f64 + 1.0) * CHUNK_NOISE_BASE_BOUNDS * zoom;
         let end_y = (y as f64 + 1.0) * CHUNK_NOISE_BASE_BOUNDS * zoom;
 
-        let fbm = Fbm::<Perlin>::new(seed);
+        // Every octave is seeded with the next seed, the highest ones would overflow.
+        let fbm = Fbm::<Perlin>::new(seed.min(u32::MAX - Fbm::<Perlin>::DEFAULT_OCTAVE_COUNT as u32));
 
         PlaneMapBuilder::<_, 2>::new(fbm)
-            .set_size(CHUNK_SIZE, CHUNK_SIZE)
+            .set_size(samples, samples)
             .set_x_bounds(start_x, end_x)
             .set_y_bounds(start_y, end_y)
             .build()
@@ -34,6 +48,6 @@ impl HeightMap {
 
 impl Noise2D<u8> for HeightMap {
     fn get(&self, x: i32, z: i32) -> u8 {
-        ((self.noise.get_value(x as usize, z as usize) as f32 / 2.0 + 1.0) * VERTICAL_SCALE).round() as u8
+        ((self.noise.get_value(x as usize / self.step, z as usize / self.step) as f32 / 2.0 + 1.0) * self.scale).round() as u8
     }
 }
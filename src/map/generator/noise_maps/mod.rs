@@ -4,11 +4,13 @@ mod biome_map;
 mod topping_map;
 mod cave_map;
 mod resource_map;
+mod value_map;
 
 pub use height_map::*;
 pub use biome_map::*;
 pub use topping_map::*;
 pub use cave_map::*;
 pub use resource_map::*;
-pub use utils::{VERTICAL_SCALE, Noise2D, Noise3D};
+pub use value_map::*;
+pub use utils::{NOISE_SCALE, Noise2D, Noise3D};
 
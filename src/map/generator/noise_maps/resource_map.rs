@@ -2,10 +2,7 @@ use noise::core::perlin::perlin_3d;
 use noise::permutationtable::PermutationTable;
 use crate::map::chunk::BlockType;
 use crate::map::generator::noise_maps::Noise3D;
-
-const IRON_PROBABILITY: f64 = 0.1;
-const COPPER_PROBABILITY: f64 = 0.1;
-const COAL_PROBABILITY: f64 = 0.1;
+use crate::map::generator::WorldGenConfig;
 
 pub struct ResourceMap {
     iron_table: PermutationTable,
@@ -14,17 +11,24 @@ pub struct ResourceMap {
     ch_x: f64,
     ch_z: f64,
     zoom: f64,
+    iron_probability: f64,
+    copper_probability: f64,
+    coal_probability: f64,
 }
 
 impl ResourceMap {
-    pub fn new(x: i32, z: i32, zoom: f64, seed: u32) -> ResourceMap {
+    pub fn new(x: i32, z: i32, seed: u32, config: &WorldGenConfig) -> ResourceMap {
+        let zoom = config.min_zoom * config.resource_zoom;
         ResourceMap {
             iron_table: PermutationTable::new(seed),
-            copper_table: PermutationTable::new(seed + 9865),
-            coal_table: PermutationTable::new(seed + 452),
+            copper_table: PermutationTable::new(seed.wrapping_add(9865)),
+            coal_table: PermutationTable::new(seed.wrapping_add(452)),
             ch_x: x as f64 * zoom,
             ch_z: z as f64 * zoom,
             zoom,
+            iron_probability: config.iron_probability,
+            copper_probability: config.copper_probability,
+            coal_probability: config.coal_probability,
         }
     }
 }
@@ -34,17 +38,17 @@ impl Noise3D<&'static BlockType> for ResourceMap {
         let (fx, fy, fz) = self.get_pos(x, y, z);
 
         let iron = (perlin_3d([fx, fy, fz], &self.iron_table) + 1.0) / 2.0;
-        if iron < IRON_PROBABILITY {
+        if iron < self.iron_probability {
             return &BlockType::IRON;
         }
 
         let copper = (perlin_3d([fx, fy, fz], &self.copper_table) + 1.0) / 2.0;
-        if copper < COPPER_PROBABILITY {
+        if copper < self.copper_probability {
             return &BlockType::COPPER;
         }
 
         let coal = (perlin_3d([fx, fy, fz], &self.coal_table) + 1.0) / 2.0;
-        if coal < COAL_PROBABILITY {
+        if coal < self.coal_probability {
             return &BlockType::COAL;
         }
 
@@ -1,23 +1,32 @@
-use crate::map::generator::biome::{Biome, OCEAN_HEIGHT};
-use super::{Noise2D, HeightMap, BiomeMap};
+use crate::map::generator::biome::Biome;
+use crate::map::generator::WorldGenConfig;
+use super::{Noise2D, HeightMap, NOISE_SCALE};
+
+/// Most blocks a topping adds above the ground, its noise goes up to one and a half [`NOISE_SCALE`].
+pub const MAX_TOPPING_HEIGHT: u8 = (NOISE_SCALE * 1.5) as u8 / 40 + 2;
 
 pub struct ToppingMap<'a> {
     desert_noise: HeightMap,
     forest_noise: HeightMap,
     mountain_noise: HeightMap,
-    height_map: &'a HeightMap,
-    biome_map: &'a BiomeMap<'a>,
+    height_map: &'a (dyn Noise2D<u8> + Sync),
+    biome_map: &'a (dyn Noise2D<Biome> + Sync),
+    ocean_height: u8,
 }
 
 impl ToppingMap<'_> {
-    pub fn new<'a>(x: i32, z: i32, zoom: f64, seed: u32, height_map: &'a HeightMap, biome_map: &'a BiomeMap) -> ToppingMap<'a>
+    pub fn new<'a>(x: i32, z: i32, seed: u32, samples: usize,
+                   height_map: &'a (dyn Noise2D<u8> + Sync), biome_map: &'a (dyn Noise2D<Biome> + Sync),
+                   config: &WorldGenConfig) -> ToppingMap<'a>
     {
+        let zoom = config.min_zoom * config.topping_zoom;
         ToppingMap {
-            desert_noise: HeightMap::new(x, z, zoom * 10.0, seed),
-            mountain_noise: HeightMap::new(x, z, zoom * 100.0, seed),
-            forest_noise: HeightMap::new(x, z, zoom * 50.0, seed),
+            desert_noise: HeightMap::with_samples(x, z, zoom * 10.0, seed, samples, NOISE_SCALE),
+            mountain_noise: HeightMap::with_samples(x, z, zoom * 100.0, seed, samples, NOISE_SCALE),
+            forest_noise: HeightMap::with_samples(x, z, zoom * 50.0, seed, samples, NOISE_SCALE),
             height_map,
             biome_map,
+            ocean_height: config.ocean_height,
         }
     }
 }
@@ -25,14 +34,14 @@ impl ToppingMap<'_> {
 impl Noise2D<u8> for ToppingMap<'_> {
     fn get(&self, x: i32, z: i32) -> u8 {
         match self.biome_map.get(x, z) {
-            Biome::Tundra      => self.height_map.get(x, z) + self.forest_noise.get(x, z) / 40   + 2,
-            Biome::Plains      => self.height_map.get(x, z) + self.desert_noise.get(x, z) / 40   + 2,
-            Biome::Forest      => self.height_map.get(x, z) + self.forest_noise.get(x, z) / 40   + 2,
-            Biome::Desert      => self.height_map.get(x, z) + self.desert_noise.get(x, z) / 40   + 2,
-            Biome::Mountain    => self.height_map.get(x, z) + self.mountain_noise.get(x, z) / 40,
-            Biome::IcePike     => self.height_map.get(x, z) + self.mountain_noise.get(x, z) / 40,
-            Biome::FrozenOcean => OCEAN_HEIGHT              + self.desert_noise.get(x, z) / 40   + 2,
-            Biome::Ocean       => OCEAN_HEIGHT                                                   + 3,
+            Biome::Tundra      => self.height_map.get(x, z).saturating_add(self.forest_noise.get(x, z) / 40   + 2),
+            Biome::Plains      => self.height_map.get(x, z).saturating_add(self.desert_noise.get(x, z) / 40   + 2),
+            Biome::Forest      => self.height_map.get(x, z).saturating_add(self.forest_noise.get(x, z) / 40   + 2),
+            Biome::Desert      => self.height_map.get(x, z).saturating_add(self.desert_noise.get(x, z) / 40   + 2),
+            Biome::Mountain    => self.height_map.get(x, z).saturating_add(self.mountain_noise.get(x, z) / 40),
+            Biome::IcePike     => self.height_map.get(x, z).saturating_add(self.mountain_noise.get(x, z) / 40),
+            Biome::FrozenOcean => self.ocean_height         .saturating_add(self.desert_noise.get(x, z) / 40   + 2),
+            Biome::Ocean       => self.ocean_height         .saturating_add(3),
         }
     }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use crate::utils::CHUNK_SIZE_F64;
 
-pub const VERTICAL_SCALE: f32 = 100.0;
+/// Range of the noise maps that aren't terrain heights, like temperatures.
+pub const NOISE_SCALE: f32 = 100.0;
 
 pub trait Noise2D<T> {
     fn get(&self, x: i32, z: i32) -> T;
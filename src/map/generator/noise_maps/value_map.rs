@@ -0,0 +1,11 @@
+use crate::utils::CHUNK_SIZE;
+use super::Noise2D;
+
+/// Values of every block of a column given up front instead of computed from noise, row by row.
+pub struct ValueMap<T>(pub Vec<T>);
+
+impl<T: Copy> Noise2D<T> for ValueMap<T> {
+    fn get(&self, x: i32, z: i32) -> T {
+        self.0[z as usize * CHUNK_SIZE + x as usize]
+    }
+}
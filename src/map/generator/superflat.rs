@@ -0,0 +1,127 @@
+use bevy::prelude::Color;
+use crate::map::chunk::{BlockRegistry, BlockType, Chunk};
+use crate::utils::{BlockPos, CHUNK_SIZE, CHUNK_SIZE_I32};
+use super::{WorldGenerator, MAP_HEIGHT};
+
+/// Flat world of the same layers everywhere, from the bottom up.
+#[derive(Clone)]
+pub struct SuperflatGenerator {
+    layers: Vec<(usize, &'static BlockType)>,
+    column: Vec<Chunk>,
+}
+
+#[derive(Debug)]
+pub enum SuperflatPresetError {
+    /// A layer isn't `count*name` or `name`.
+    InvalidLayer(String),
+    UnknownBlock(String),
+    /// The layers are higher than [`MAP_HEIGHT`].
+    TooHigh(usize),
+}
+
+impl SuperflatGenerator {
+    /// Parses a preset like `"1*unbreakable,60*stone,3*dirt"`, listing layers from the bottom up
+    /// as block names with optional thickness. Names are looked up in `registry` ignoring case.
+    ///
+    /// Generators are built before the app and its registry exist, so only the blocks of
+    /// [`BlockRegistry::new`], the built-in ones, can be used unless definitions are loaded into it first.
+    pub fn from_preset(preset: &str, registry: &BlockRegistry) -> Result<Self, SuperflatPresetError> {
+        let mut layers = Vec::new();
+        for layer in preset.split(',').map(str::trim) {
+            let (count, name) = match layer.split_once('*') {
+                Some((count, name)) => {
+                    let count = count.trim().parse::<usize>()
+                        .map_err(|_| SuperflatPresetError::InvalidLayer(layer.to_string()))?;
+                    (count, name.trim())
+                }
+                None => (1, layer),
+            };
+            if name.is_empty() {
+                return Err(SuperflatPresetError::InvalidLayer(layer.to_string()));
+            }
+            let block_type = registry.get_by_name(name)
+                .ok_or_else(|| SuperflatPresetError::UnknownBlock(name.to_string()))?;
+            layers.push((count, block_type));
+        }
+
+        let height: usize = layers.iter().map(|(count, _)| count).sum();
+        if height > MAP_HEIGHT {
+            return Err(SuperflatPresetError::TooHigh(height));
+        }
+
+        Ok(SuperflatGenerator {
+            column: Self::create_column(&layers),
+            layers,
+        })
+    }
+
+    fn create_column(layers: &[(usize, &'static BlockType)]) -> Vec<Chunk> {
+        let mut column: Vec<_> = (0..MAP_HEIGHT / CHUNK_SIZE).map(|_| Chunk::new()).collect();
+        let blocks = layers.iter().flat_map(|&(count, block_type)| std::iter::repeat_n(block_type, count));
+        for (y, block_type) in blocks.enumerate() {
+            let chunk = &mut column[y / CHUNK_SIZE];
+            for z in 0..CHUNK_SIZE_I32 {
+                for x in 0..CHUNK_SIZE_I32 {
+                    unsafe {
+                        chunk.spawn_block_unchecked(&BlockPos::new(x, (y % CHUNK_SIZE) as i32, z), block_type);
+                    }
+                }
+            }
+        }
+        column
+    }
+
+    fn get_surface_height(&self) -> usize {
+        self.layers.iter().map(|(count, _)| count).sum()
+    }
+}
+
+impl WorldGenerator for SuperflatGenerator {
+    fn get_chunk_column(&self, _ch_x: i32, _ch_z: i32) -> Vec<Chunk> {
+        self.column.clone()
+    }
+
+    fn get_column_surface(&self, _ch_x: i32, _ch_z: i32, samples: usize) -> Option<Vec<(u8, Color)>> {
+        let (_, top) = self.layers.iter().rev().find(|(count, _)| *count > 0)?;
+        let height = self.get_surface_height().min(u8::MAX as usize) as u8;
+        Some(vec![(height, top.color); samples * samples])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(preset: &str) -> Result<SuperflatGenerator, SuperflatPresetError> {
+        SuperflatGenerator::from_preset(preset, &BlockRegistry::new())
+    }
+
+    #[test]
+    fn layers_are_stacked_from_the_bottom() {
+        let generator = parse("1*unbreakable, 60*Stone,3*dirt,sand").unwrap();
+        assert_eq!(generator.get_surface_height(), 65);
+
+        let column = generator.get_chunk_column(0, 0);
+        assert_eq!(column.len(), MAP_HEIGHT / CHUNK_SIZE);
+        let block_at = |y: usize| column[y / CHUNK_SIZE].get_block_at(&BlockPos::new(5, (y % CHUNK_SIZE) as i32, 7))
+            .unwrap()
+            .map(|block| block.block_type.name);
+        assert_eq!(block_at(0), Some("Unbreakable"));
+        assert_eq!(block_at(1), Some("Stone"));
+        assert_eq!(block_at(60), Some("Stone"));
+        assert_eq!(block_at(61), Some("Dirt"));
+        assert_eq!(block_at(64), Some("Sand"));
+        assert_eq!(block_at(65), None);
+        assert_eq!(column[3].get_amount_of_blocks(), 0);
+    }
+
+    #[test]
+    fn invalid_presets_are_errors() {
+        assert!(matches!(parse("x*stone"), Err(SuperflatPresetError::InvalidLayer(layer)) if layer == "x*stone"));
+        assert!(matches!(parse("3*"), Err(SuperflatPresetError::InvalidLayer(_))));
+        assert!(matches!(parse("stone,,dirt"), Err(SuperflatPresetError::InvalidLayer(_))));
+        assert!(matches!(parse("3*marble"), Err(SuperflatPresetError::UnknownBlock(name)) if name == "marble"));
+        assert!(matches!(parse("200*stone,57*dirt"), Err(SuperflatPresetError::TooHigh(257))));
+        assert!(parse("200*stone,56*dirt").is_ok());
+    }
+}
@@ -0,0 +1,260 @@
+use std::path::Path;
+use bevy::prelude::Resource;
+use ron::Value;
+use serde::{Deserialize, Serialize};
+use crate::utils::CHUNK_SIZE_F64;
+use super::noise_maps::MAX_TOPPING_HEIGHT;
+
+/// Tuning of the noise terrain. Heights are in blocks, temperatures and probabilities are
+/// compared with noise values, zooms scale how fast the noise changes along the world.
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorldGenConfig {
+    /// Replaces the seed the world was created with.
+    pub seed: Option<u32>,
+
+    /// Average height of the terrain, it goes from half of it to one and a half of it.
+    pub vertical_scale: f32,
+    /// Zoom of the height noise, the other zooms are multiples of it.
+    pub min_zoom: f64,
+    pub biome_zoom: f64,
+    pub topping_zoom: f64,
+    pub cave_zoom: f64,
+    pub resource_zoom: f64,
+
+    /// Sea level, lower columns are oceans.
+    pub ocean_height: u8,
+    pub mountain_height: u8,
+    pub ice_pike_height: u8,
+    pub max_desert_height: u8,
+
+    /// Temperatures below this one are frozen.
+    pub freeze_temperature: u8,
+    pub forest_temperature: u8,
+    pub plains_temperature: u8,
+
+    pub cave_probability: f64,
+    pub iron_probability: f64,
+    pub copper_probability: f64,
+    pub coal_probability: f64,
+}
+
+#[derive(Debug)]
+pub enum WorldGenConfigError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    /// The file isn't a struct like `(preset: "amplified", cave_probability: 0.4)`,
+    /// or its values can't generate a terrain.
+    InvalidConfig(String),
+    UnknownPreset(String),
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        WorldGenConfig {
+            seed: None,
+
+            vertical_scale: 100.0,
+            min_zoom: 0.01 * 64.0 / CHUNK_SIZE_F64,
+            biome_zoom: 2.0,
+            topping_zoom: 1.0,
+            cave_zoom: 5.0,
+            resource_zoom: 5.0,
+
+            ocean_height: 127 - 25,
+            mountain_height: 127 + 70,
+            ice_pike_height: 127 + 80,
+            max_desert_height: 127 + 60,
+
+            freeze_temperature: 50,
+            forest_temperature: 100,
+            plains_temperature: 127,
+
+            cave_probability: 0.3,
+            iron_probability: 0.1,
+            copper_probability: 0.1,
+            coal_probability: 0.1,
+        }
+    }
+}
+
+impl WorldGenConfig {
+    pub const PRESETS: [&'static str; 3] = ["default", "amplified", "large_biomes"];
+
+    /// Higher mountains and deeper valleys, with the biome heights raised to match.
+    pub fn amplified() -> Self {
+        WorldGenConfig {
+            vertical_scale: 140.0,
+            ocean_height: 140 - 25,
+            mountain_height: 140 + 50,
+            ice_pike_height: 140 + 60,
+            max_desert_height: 140 + 40,
+            ..Self::default()
+        }
+    }
+
+    /// Biomes four times as wide.
+    pub fn large_biomes() -> Self {
+        WorldGenConfig {
+            biome_zoom: 0.5,
+            ..Self::default()
+        }
+    }
+
+    /// Preset named like in [`Self::PRESETS`].
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::default()),
+            "amplified" => Some(Self::amplified()),
+            "large_biomes" => Some(Self::large_biomes()),
+            _ => None,
+        }
+    }
+
+    /// Reads a RON config file.
+    pub fn load(path: &Path) -> Result<Self, WorldGenConfigError> {
+        let content = std::fs::read_to_string(path).map_err(WorldGenConfigError::Io)?;
+        Self::load_str(&content)
+    }
+
+    /// Parses a config naming a preset, `"default"` if it doesn't, and the values that differ from it.
+    pub fn load_str(content: &str) -> Result<Self, WorldGenConfigError> {
+        let mut overrides = match ron::from_str(content).map_err(WorldGenConfigError::Parse)? {
+            Value::Map(overrides) => overrides,
+            // An empty struct is read as a unit.
+            Value::Unit => ron::Map::new(),
+            _ => return Err(WorldGenConfigError::InvalidConfig(content.to_string())),
+        };
+        let preset = match overrides.remove(&Value::String("preset".to_string())) {
+            Some(Value::String(name)) => Self::preset(&name).ok_or(WorldGenConfigError::UnknownPreset(name))?,
+            Some(value) => return Err(WorldGenConfigError::InvalidConfig(format!("{value:?}"))),
+            None => Self::default(),
+        };
+
+        let mut config = Self::to_value(&preset);
+        for (key, value) in overrides.iter() {
+            let value = match value {
+                // The seed can be written without `Some`.
+                Value::Number(_) if *key == Value::String("seed".to_string()) => Value::Option(Some(Box::new(value.clone()))),
+                _ => value.clone(),
+            };
+            config.insert(key.clone(), value);
+        }
+        let config: Self = Value::Map(config).into_rust().map_err(|err| WorldGenConfigError::InvalidConfig(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the terrain fits in the world, the zooms and probabilities are usable and
+    /// the biome thresholds are in order, so none of them is left without temperatures or heights.
+    pub fn validate(&self) -> Result<(), WorldGenConfigError> {
+        let invalid = |message: String| Err(WorldGenConfigError::InvalidConfig(message));
+        // The terrain goes up to one and a half of the scale, and toppings are added above it.
+        let max_scale = (u8::MAX - MAX_TOPPING_HEIGHT) as f32 / 1.5;
+        if !(self.vertical_scale > 0.0 && self.vertical_scale <= max_scale) {
+            return invalid(format!("vertical_scale {} isn't in (0, {max_scale}]", self.vertical_scale));
+        }
+        let zooms = [
+            ("min_zoom", self.min_zoom),
+            ("biome_zoom", self.biome_zoom),
+            ("topping_zoom", self.topping_zoom),
+            ("cave_zoom", self.cave_zoom),
+            ("resource_zoom", self.resource_zoom),
+        ];
+        for (name, zoom) in zooms {
+            if !(zoom > 0.0 && zoom.is_finite()) {
+                return invalid(format!("{name} {zoom} isn't positive"));
+            }
+        }
+        // Every biome takes the temperatures or heights between its threshold and the next one.
+        let thresholds = [
+            ("freeze_temperature", self.freeze_temperature, "forest_temperature", self.forest_temperature),
+            ("forest_temperature", self.forest_temperature, "plains_temperature", self.plains_temperature),
+            ("ocean_height", self.ocean_height, "mountain_height", self.mountain_height),
+            ("mountain_height", self.mountain_height, "ice_pike_height", self.ice_pike_height),
+            ("ocean_height", self.ocean_height, "max_desert_height", self.max_desert_height),
+        ];
+        for (lower_name, lower, higher_name, higher) in thresholds {
+            if lower >= higher {
+                return invalid(format!("{lower_name} {lower} isn't below {higher_name} {higher}"));
+            }
+        }
+        let probabilities = [
+            ("cave_probability", self.cave_probability),
+            ("iron_probability", self.iron_probability),
+            ("copper_probability", self.copper_probability),
+            ("coal_probability", self.coal_probability),
+        ];
+        for (name, probability) in probabilities {
+            if !(0.0..=1.0).contains(&probability) {
+                return invalid(format!("{name} {probability} isn't in [0, 1]"));
+            }
+        }
+        Ok(())
+    }
+
+    fn to_value(&self) -> ron::Map {
+        let content = ron::to_string(self).unwrap();
+        match ron::from_str(&content).unwrap() {
+            Value::Map(map) => map,
+            _ => unreachable!("configs are serialized as structs"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid() {
+        for name in WorldGenConfig::PRESETS {
+            let config = WorldGenConfig::preset(name).unwrap();
+            assert!(config.validate().is_ok(), "{name} preset is invalid");
+        }
+    }
+
+    #[test]
+    fn overrides_are_merged_over_the_preset() {
+        assert_eq!(WorldGenConfig::load_str("()").unwrap(), WorldGenConfig::default());
+        assert_eq!(WorldGenConfig::load_str(r#"(preset: "large_biomes")"#).unwrap(), WorldGenConfig::large_biomes());
+
+        let config = WorldGenConfig::load_str(r#"(preset: "amplified", seed: 42, cave_probability: 0.5)"#).unwrap();
+        assert_eq!(config, WorldGenConfig {
+            seed: Some(42),
+            cave_probability: 0.5,
+            ..WorldGenConfig::amplified()
+        });
+
+        let config = WorldGenConfig::load_str("(seed: Some(7), ocean_height: 90)").unwrap();
+        assert_eq!(config, WorldGenConfig { seed: Some(7), ocean_height: 90, ..WorldGenConfig::default() });
+    }
+
+    #[test]
+    fn invalid_configs_are_errors() {
+        let load = WorldGenConfig::load_str;
+        assert!(matches!(load(r#"(preset: "huge")"#), Err(WorldGenConfigError::UnknownPreset(name)) if name == "huge"));
+        assert!(matches!(load("(preset: 3)"), Err(WorldGenConfigError::InvalidConfig(_))));
+        assert!(matches!(load("(cave_size: 3)"), Err(WorldGenConfigError::InvalidConfig(_))));
+        assert!(matches!(load("[1, 2]"), Err(WorldGenConfigError::InvalidConfig(_))));
+        assert!(matches!(load("(seed: "), Err(WorldGenConfigError::Parse(_))));
+
+        for overrides in [
+            "(vertical_scale: 200.0)",
+            "(vertical_scale: 0.0)",
+            "(biome_zoom: 0.0)",
+            "(min_zoom: -0.01)",
+            "(mountain_height: 220)",
+            "(mountain_height: 207)",
+            "(ocean_height: 197)",
+            "(max_desert_height: 102)",
+            "(freeze_temperature: 100)",
+            "(plains_temperature: 90)",
+            "(iron_probability: 1.5)",
+            "(cave_probability: -0.1)",
+        ] {
+            assert!(matches!(load(overrides), Err(WorldGenConfigError::InvalidConfig(_))), "{overrides} is accepted");
+        }
+        assert!(load("(vertical_scale: 160.0)").is_ok());
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+use bevy::prelude::{Color, Resource};
+use crate::map::chunk::Chunk;
+use super::{WorldGenConfig, MAP_HEIGHT};
+
+/// Source of the terrain of new chunk columns.
+pub trait WorldGenerator: Send + Sync {
+    /// Chunks of the column from the bottom up.
+    fn get_chunk_column(&self, ch_x: i32, ch_z: i32) -> Vec<Chunk>;
+
+    /// Height of the generated columns in blocks.
+    fn get_height(&self) -> usize {
+        MAP_HEIGHT
+    }
+
+    /// Height and color of the surface at `samples` × `samples` points spread evenly over the column,
+    /// row by row, for the far terrain. `None` if it can't be found without generating the column.
+    fn get_column_surface(&self, _ch_x: i32, _ch_z: i32, _samples: usize) -> Option<Vec<(u8, Color)>> {
+        None
+    }
+
+    /// Tuning of the noise the terrain is made of, `None` if it isn't made of noise.
+    fn get_config(&self) -> Option<&WorldGenConfig> {
+        None
+    }
+}
+
+/// Generator used for all new columns, chosen when [`crate::map::MapGenerationPlugin`] is created.
+#[derive(Resource, Clone)]
+pub struct ActiveWorldGenerator(pub Arc<dyn WorldGenerator>);
@@ -0,0 +1,431 @@
+use std::collections::VecDeque;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use crate::map::chunk::{Block, BlockType, Chunk, Light, LightChannel};
+use crate::map::voxel_world::{BlockChanged, ChunkIndex};
+use crate::utils::{BlockPos, ChunkPos, WorldBlockPos, CHUNK_SIZE, CHUNK_SIZE_I32, NEIGHBOUR_OFFSETS};
+
+/// Spreads light between spawned chunks and relights the world around changed blocks.
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(PostUpdate, (light_spawned_chunks, relight_changed_blocks).chain().in_set(LightingSystems));
+    }
+}
+
+/// Systems changing light of the loaded chunks. Meshing should run after them.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LightingSystems;
+
+/// Index of the bottom neighbour in [`NEIGHBOUR_OFFSETS`].
+const BOTTOM: usize = 1;
+
+/// Blocks and light of cells that may lie in different chunks.
+trait LightAccess {
+    /// Block and light of the cell, `None` if it is not loaded.
+    fn get_cell(&self, pos: &WorldBlockPos) -> Option<(Option<Block>, Light)>;
+    fn set_light(&mut self, pos: &WorldBlockPos, light: Light);
+}
+
+/// Chunks of a single column, bottom first.
+struct ColumnLight<'a> {
+    x: i32,
+    z: i32,
+    chunks: &'a mut [Chunk],
+}
+
+impl ColumnLight<'_> {
+    fn get_chunk_index(&self, pos: &WorldBlockPos) -> Option<usize> {
+        let chunk_pos = ChunkPos::from(*pos);
+        (chunk_pos.0.x == self.x && chunk_pos.0.z == self.z
+            && (0..self.chunks.len() as i32).contains(&chunk_pos.0.y))
+            .then_some(chunk_pos.0.y as usize)
+    }
+}
+
+impl LightAccess for ColumnLight<'_> {
+    fn get_cell(&self, pos: &WorldBlockPos) -> Option<(Option<Block>, Light)> {
+        let chunk = &self.chunks[self.get_chunk_index(pos)?];
+        let block_pos = BlockPos::from(*pos);
+        Some((*chunk.get_block_at(&block_pos).unwrap(), chunk.get_light_at(&block_pos).unwrap()))
+    }
+
+    fn set_light(&mut self, pos: &WorldBlockPos, light: Light) {
+        if let Some(i) = self.get_chunk_index(pos) {
+            self.chunks[i].set_light(&BlockPos::from(*pos), light).unwrap();
+        }
+    }
+}
+
+/// Light access to the loaded world.
+#[derive(SystemParam)]
+struct LightWorld<'w, 's> {
+    index: Res<'w, ChunkIndex>,
+    chunks: Query<'w, 's, &'static mut Chunk>,
+}
+
+impl LightAccess for LightWorld<'_, '_> {
+    fn get_cell(&self, pos: &WorldBlockPos) -> Option<(Option<Block>, Light)> {
+        let chunk = self.chunks.get(self.index.get(&(*pos).into())?).ok()?;
+        let block_pos = BlockPos::from(*pos);
+        Some((*chunk.get_block_at(&block_pos).unwrap(), chunk.get_light_at(&block_pos).unwrap()))
+    }
+
+    fn set_light(&mut self, pos: &WorldBlockPos, light: Light) {
+        let Some(entity) = self.index.get(&(*pos).into()) else {
+            return;
+        };
+        let Ok(mut chunk) = self.chunks.get_mut(entity) else {
+            return;
+        };
+        let block_pos = BlockPos::from(*pos);
+        // Avoid marking the chunk as changed when nothing changes.
+        if chunk.get_light_at(&block_pos).unwrap() != light {
+            chunk.set_light(&block_pos, light).unwrap();
+        }
+    }
+}
+
+fn is_opaque(block: Option<Block>) -> bool {
+    block.is_some_and(|block| block.block_type.is_opaque)
+}
+
+fn emission(block: Option<Block>, channel: LightChannel) -> u8 {
+    match (block, channel) {
+        (Some(block), LightChannel::Block) => block.block_type.light_emission,
+        _ => 0,
+    }
+}
+
+/// Level of `level` light after moving to the neighbour at `NEIGHBOUR_OFFSETS[side]`.
+/// Full sky light goes down without fading.
+fn spread_level(channel: LightChannel, level: u8, side: usize) -> u8 {
+    if channel == LightChannel::Sky && side == BOTTOM && level == BlockType::MAX_LIGHT {
+        level
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+/// Spreads the light of `queue` cells to the cells around them.
+fn propagate(world: &mut impl LightAccess, channel: LightChannel, mut queue: VecDeque<WorldBlockPos>) {
+    while let Some(pos) = queue.pop_front() {
+        let Some((_, light)) = world.get_cell(&pos) else {
+            continue;
+        };
+        let level = light.get(channel);
+        if level <= 1 {
+            continue;
+        }
+
+        for (side, offset) in NEIGHBOUR_OFFSETS.iter().enumerate() {
+            let neighbour = WorldBlockPos(pos.0 + *offset);
+            let Some((block, neighbour_light)) = world.get_cell(&neighbour) else {
+                continue;
+            };
+            let spread = spread_level(channel, level, side);
+            if is_opaque(block) || neighbour_light.get(channel) >= spread {
+                continue;
+            }
+            world.set_light(&neighbour, neighbour_light.with(channel, spread));
+            queue.push_back(neighbour);
+        }
+    }
+}
+
+/// Darkens the cells lit by `queue` cells, which are already darkened from the given levels,
+/// then spreads the light back from the brighter cells around.
+fn unpropagate(world: &mut impl LightAccess, channel: LightChannel, mut queue: VecDeque<(WorldBlockPos, u8)>) {
+    let mut relight = VecDeque::new();
+    while let Some((pos, level)) = queue.pop_front() {
+        for (side, offset) in NEIGHBOUR_OFFSETS.iter().enumerate() {
+            let neighbour = WorldBlockPos(pos.0 + *offset);
+            let Some((block, neighbour_light)) = world.get_cell(&neighbour) else {
+                continue;
+            };
+            let neighbour_level = neighbour_light.get(channel);
+            if neighbour_level == 0 {
+                continue;
+            }
+
+            if neighbour_level < level || neighbour_level == spread_level(channel, level, side) {
+                // The neighbour might have been lit by the darkened cell.
+                let emitted = emission(block, channel);
+                world.set_light(&neighbour, neighbour_light.with(channel, emitted));
+                queue.push_back((neighbour, neighbour_level));
+                if emitted > 0 {
+                    relight.push_back(neighbour);
+                }
+            } else {
+                relight.push_back(neighbour);
+            }
+        }
+    }
+    propagate(world, channel, relight);
+}
+
+/// Updates light around `pos` after its block has changed to `current`.
+fn relight_block(world: &mut impl LightAccess, pos: &WorldBlockPos, current: Option<Block>) {
+    for channel in LightChannel::ALL {
+        let Some((_, light)) = world.get_cell(pos) else {
+            return;
+        };
+        let emitted = emission(current, channel);
+        world.set_light(pos, light.with(channel, emitted));
+        unpropagate(world, channel, VecDeque::from([(*pos, light.get(channel))]));
+
+        // The cells around light the changed one again unless it has become opaque.
+        let mut queue = VecDeque::from(pos.neighbours());
+        if emitted > 0 {
+            queue.push_back(*pos);
+        }
+        propagate(world, channel, queue);
+    }
+}
+
+/// Lights a freshly generated or loaded column of chunks, bottom first.
+/// Light coming from the neighbour columns is spread once the column is spawned.
+pub fn light_column(x: i32, z: i32, chunks: &mut [Chunk]) {
+    // Chunks above the highest block are in the sky, the rest are lit from scratch.
+    let top = chunks.iter().rposition(|chunk| chunk.get_amount_of_blocks() > 0).map_or(0, |i| i + 1);
+    for (i, chunk) in chunks.iter_mut().enumerate() {
+        chunk.fill_light(if i >= top { Light::SKY } else { Light::DARK });
+    }
+
+    let emitters: Vec<_> = chunks.iter().enumerate()
+        .filter(|(_, chunk)| chunk.storage().palette().iter().any(|block| emission(*block, LightChannel::Block) > 0))
+        .flat_map(|(ch_y, chunk)| chunk.iter_with_pos()
+            .filter(|(_, block)| emission(**block, LightChannel::Block) > 0)
+            .map(move |(pos, block)| (WorldBlockPos::from(&ChunkPos::new(x, ch_y as i32, z), &pos), *block)))
+        .collect();
+
+    let mut world = ColumnLight { x, z, chunks };
+    let origin = WorldBlockPos::from(&ChunkPos::new(x, 0, z), &BlockPos::new(0, 0, 0)).0;
+
+    // Sky light goes straight down to the first opaque block.
+    let mut sky_heights = [[0; CHUNK_SIZE]; CHUNK_SIZE];
+    for (bx, row) in sky_heights.iter_mut().enumerate() {
+        for (bz, sky_height) in row.iter_mut().enumerate() {
+            let mut y = (top * CHUNK_SIZE) as i32;
+            while y > 0 {
+                let pos = WorldBlockPos(origin + IVec3::new(bx as i32, y - 1, bz as i32));
+                let (block, light) = world.get_cell(&pos).unwrap();
+                if is_opaque(block) {
+                    break;
+                }
+                world.set_light(&pos, light.with(LightChannel::Sky, BlockType::MAX_LIGHT));
+                y -= 1;
+            }
+            *sky_height = y;
+        }
+    }
+
+    // Then sideways from the cells next to shaded ones.
+    let mut queue = VecDeque::new();
+    for bx in 0..CHUNK_SIZE_I32 {
+        for bz in 0..CHUNK_SIZE_I32 {
+            let sky_height = sky_heights[bx as usize][bz as usize];
+            let shade_height = NEIGHBOUR_OFFSETS[2..].iter()
+                .map(|offset| (bx + offset.x, bz + offset.z))
+                .filter(|&(nx, nz)| (0..CHUNK_SIZE_I32).contains(&nx) && (0..CHUNK_SIZE_I32).contains(&nz))
+                .map(|(nx, nz)| sky_heights[nx as usize][nz as usize])
+                .max()
+                .unwrap_or(0);
+            queue.extend((sky_height..shade_height).map(|y| WorldBlockPos(origin + IVec3::new(bx, y, bz))));
+        }
+    }
+    propagate(&mut world, LightChannel::Sky, queue);
+
+    let mut queue = VecDeque::new();
+    for (pos, block) in emitters {
+        let (_, light) = world.get_cell(&pos).unwrap();
+        world.set_light(&pos, light.with(LightChannel::Block, emission(block, LightChannel::Block)));
+        queue.push_back(pos);
+    }
+    propagate(&mut world, LightChannel::Block, queue);
+
+    for chunk in world.chunks.iter_mut() {
+        chunk.reset_updated();
+    }
+}
+
+/// Spreads light across the sides spawned chunks share with their horizontal neighbours.
+/// Chunks are spawned with their position, which is filtered on since the chunks are borrowed mutably.
+fn light_spawned_chunks(spawned: Query<&ChunkPos, Added<ChunkPos>>,
+                        mut world: LightWorld) {
+    let mut queues = LightChannel::ALL.map(|_| VecDeque::new());
+    for chunk_pos in &spawned {
+        for offset in &NEIGHBOUR_OFFSETS[2..] {
+            if !world.index.contains(&ChunkPos(chunk_pos.0 + *offset)) {
+                continue;
+            }
+
+            let axis = if offset.x != 0 { 0 } else { 2 };
+            for i in 0..CHUNK_SIZE_I32 {
+                for j in 0..CHUNK_SIZE_I32 {
+                    let mut block_pos = IVec3::new(i, j, i);
+                    block_pos[axis] = if offset[axis] > 0 { CHUNK_SIZE_I32 - 1 } else { 0 };
+                    let pos = WorldBlockPos::from(chunk_pos, &BlockPos(block_pos));
+                    let neighbour = WorldBlockPos(pos.0 + *offset);
+                    let (Some((_, light)), Some((_, neighbour_light))) = (world.get_cell(&pos), world.get_cell(&neighbour)) else {
+                        continue;
+                    };
+
+                    for (queue, channel) in queues.iter_mut().zip(LightChannel::ALL) {
+                        if light.get(channel) > neighbour_light.get(channel) + 1 {
+                            queue.push_back(pos);
+                        } else if neighbour_light.get(channel) > light.get(channel) + 1 {
+                            queue.push_back(neighbour);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for (queue, channel) in queues.into_iter().zip(LightChannel::ALL) {
+        propagate(&mut world, channel, queue);
+    }
+}
+
+fn relight_changed_blocks(mut events: EventReader<BlockChanged>,
+                          mut world: LightWorld) {
+    for event in events.read() {
+        relight_block(&mut world, &event.pos, event.current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::map::chunk::BlockRegistry;
+    use crate::map::voxel_world::VoxelWorldPlugin;
+    use super::*;
+
+    impl LightAccess for HashMap<ChunkPos, Chunk> {
+        fn get_cell(&self, pos: &WorldBlockPos) -> Option<(Option<Block>, Light)> {
+            let chunk = self.get(&(*pos).into())?;
+            let block_pos = BlockPos::from(*pos);
+            Some((*chunk.get_block_at(&block_pos).unwrap(), chunk.get_light_at(&block_pos).unwrap()))
+        }
+
+        fn set_light(&mut self, pos: &WorldBlockPos, light: Light) {
+            if let Some(chunk) = self.get_mut(&(*pos).into()) {
+                chunk.set_light(&BlockPos::from(*pos), light).unwrap();
+            }
+        }
+    }
+
+    fn lamp() -> &'static BlockType {
+        let mut registry = BlockRegistry::new();
+        registry.load_definitions_str(r##"[(id: 100, name: "Lamp", color: "#FFEEAA", light_emission: 14)]"##).unwrap();
+        registry.get_by_name("lamp").unwrap()
+    }
+
+    /// Lit column of two chunks with a stone floor at the bottom and `blocks` in its first chunk.
+    fn lit_column(x: i32, z: i32, blocks: &[(IVec3, &'static BlockType)]) -> HashMap<ChunkPos, Chunk> {
+        let mut chunks = vec![Chunk::new(), Chunk::new()];
+        for bz in 0..CHUNK_SIZE_I32 {
+            for bx in 0..CHUNK_SIZE_I32 {
+                chunks[0].set_block(&BlockPos::new(bx, 0, bz), &BlockType::STONE).unwrap();
+            }
+        }
+        for (pos, block_type) in blocks {
+            chunks[0].set_block(&BlockPos(*pos), block_type).unwrap();
+        }
+        light_column(x, z, &mut chunks);
+        chunks.into_iter()
+            .enumerate()
+            .map(|(ch_y, chunk)| (ChunkPos::new(x, ch_y as i32, z), chunk))
+            .collect()
+    }
+
+    fn get_level(world: &HashMap<ChunkPos, Chunk>, channel: LightChannel, x: i32, y: i32, z: i32) -> u8 {
+        world.get_cell(&WorldBlockPos::new(x, y, z)).unwrap().1.get(channel)
+    }
+
+    #[test]
+    fn sky_light_fades_under_an_overhang() {
+        let overhang: Vec<_> = (0..CHUNK_SIZE_I32)
+            .flat_map(|z| (0..16).map(move |x| (IVec3::new(x, 10, z), &BlockType::STONE)))
+            .collect();
+        let world = lit_column(0, 0, &overhang);
+
+        let sky = |x| get_level(&world, LightChannel::Sky, x, 5, 8);
+        assert_eq!(sky(20), 15);
+        assert_eq!(sky(16), 15);
+        assert_eq!(sky(15), 14);
+        assert_eq!(sky(12), 11);
+        assert_eq!(sky(1), 0);
+        assert_eq!(get_level(&world, LightChannel::Sky, 8, 11, 8), 15, "above the overhang");
+        assert_eq!(get_level(&world, LightChannel::Sky, 8, 0, 8), 0, "inside the floor");
+    }
+
+    #[test]
+    fn removed_emitter_darkens_around() {
+        let mut chunk = Chunk::new();
+        chunk.fill_light(Light::DARK);
+        let mut world = HashMap::from([(ChunkPos::new(0, 0, 0), chunk)]);
+        let pos = WorldBlockPos::new(16, 16, 16);
+        let chunk = world.get_mut(&ChunkPos::new(0, 0, 0)).unwrap();
+        chunk.set_block(&BlockPos::from(pos), lamp()).unwrap();
+        let lamp = *chunk.get_block_at(&BlockPos::from(pos)).unwrap();
+
+        relight_block(&mut world, &pos, lamp);
+        assert_eq!(get_level(&world, LightChannel::Block, 16, 16, 16), 14);
+        assert_eq!(get_level(&world, LightChannel::Block, 20, 16, 16), 10);
+        assert_eq!(get_level(&world, LightChannel::Block, 16, 16, 29), 1);
+
+        world.get_mut(&ChunkPos::new(0, 0, 0)).unwrap().remove_block(&BlockPos::from(pos)).unwrap();
+        relight_block(&mut world, &pos, None);
+        let chunk = &world[&ChunkPos::new(0, 0, 0)];
+        assert!((0..Chunk::SIZE).all(|i| chunk.get_light_at(&BlockPos::from_index(i)).unwrap() == Light::DARK));
+    }
+
+    #[test]
+    fn sky_column_is_relit_around_placed_and_removed_blocks() {
+        let mut world = lit_column(0, 0, &[]);
+        let pos = WorldBlockPos::new(5, 20, 5);
+        let sky = |world: &HashMap<ChunkPos, Chunk>, y| get_level(world, LightChannel::Sky, 5, y, 5);
+        assert_eq!(sky(&world, 2), 15);
+
+        let chunk = world.get_mut(&ChunkPos::new(0, 0, 0)).unwrap();
+        chunk.set_block(&BlockPos::from(pos), &BlockType::STONE).unwrap();
+        let stone = *chunk.get_block_at(&BlockPos::from(pos)).unwrap();
+        relight_block(&mut world, &pos, stone);
+        assert_eq!(sky(&world, 21), 15);
+        assert_eq!(sky(&world, 20), 0);
+        assert_eq!(sky(&world, 19), 14);
+        assert_eq!(sky(&world, 2), 14);
+
+        world.get_mut(&ChunkPos::new(0, 0, 0)).unwrap().remove_block(&BlockPos::from(pos)).unwrap();
+        relight_block(&mut world, &pos, None);
+        assert_eq!(sky(&world, 20), 15);
+        assert_eq!(sky(&world, 19), 15);
+        assert_eq!(sky(&world, 2), 15);
+    }
+
+    #[test]
+    fn spawned_chunks_share_light_across_their_borders() {
+        let mut app = App::new();
+        app.add_plugins((VoxelWorldPlugin, LightingPlugin));
+        let lamp_pos = IVec3::new(CHUNK_SIZE_I32 - 1, 8, 8);
+        let lit = lit_column(0, 0, &[(lamp_pos, lamp())]);
+        let dark = lit_column(1, 0, &[]);
+        assert_eq!(get_level(&dark, LightChannel::Block, CHUNK_SIZE_I32, 8, 8), 0, "columns are lit on their own");
+        for column in [lit, dark] {
+            for (pos, chunk) in column {
+                app.world_mut().spawn((chunk, pos));
+            }
+        }
+        app.update();
+
+        let mut chunks = app.world_mut().query::<(&ChunkPos, &Chunk)>();
+        let chunk = chunks.iter(app.world()).find(|(pos, _)| **pos == ChunkPos::new(1, 0, 0)).unwrap().1;
+        let block_light = |x| chunk.get_light_at(&BlockPos::new(x, 8, 8)).unwrap().get(LightChannel::Block);
+        assert_eq!(block_light(0), 13);
+        assert_eq!(block_light(3), 10);
+    }
+}
@@ -1,51 +1,166 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
 use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
 use generator::*;
-use chunk::Chunk;
+use chunk::{BlockRegistry, Chunk, ChunkInteractionPlugin};
+use config_reload::{load_noise_generator, ConfigReloadPlugin};
+use far_terrain::FarTerrainPlugin;
+use lighting::{light_column, LightingPlugin};
+use persistence::ChunkStorage;
+use voxel_world::{ChunkIndex, VoxelWorldPlugin};
 use crate::utils::{ChunkPos, CHUNK_SIZE_F32};
 
 pub mod generator;
 pub mod render;
 pub mod chunk;
+pub mod persistence;
+pub mod voxel_world;
+pub mod raycast;
+pub mod lighting;
+pub mod far_terrain;
+pub mod config_reload;
 
 const VISIBLE_CHUNKS_DISTANCE: usize = 10;
 const CHUNKS_CUT_DISTANCE: usize = 0;
+const MAX_GENERATING_COLUMNS: usize = 8;
 
 pub struct MapGenerationPlugin {
-    pub seed: u32,
+    pub generator: Arc<dyn WorldGenerator>,
+    /// Directory with region files of the world.
+    pub save_directory: PathBuf,
+    /// Config file the noise generator is rebuilt from whenever it changes, and the seed used if it names none.
+    pub config_file: Option<(PathBuf, u32)>,
+}
+
+impl MapGenerationPlugin {
+    pub fn with_generator(generator: impl WorldGenerator + 'static, save_directory: impl Into<PathBuf>) -> Self {
+        MapGenerationPlugin {
+            generator: Arc::new(generator),
+            save_directory: save_directory.into(),
+            config_file: None,
+        }
+    }
+
+    /// World of the default noise terrain.
+    pub fn with_seed(seed: u32, save_directory: impl Into<PathBuf>) -> Self {
+        Self::with_generator(NoiseGenerator { seed, config: WorldGenConfig::default() }, save_directory)
+    }
+
+    /// World of the noise terrain tuned by a [`WorldGenConfig`] file, the default one if it can't be loaded.
+    /// The file may replace `seed`, and the terrain is regenerated whenever it changes.
+    pub fn with_config_file(seed: u32, config: impl Into<PathBuf>, save_directory: impl Into<PathBuf>) -> Self {
+        let path = config.into();
+        let generator = match load_noise_generator(&path, seed) {
+            Ok(generator) => {
+                info!("Loaded world generation config from {path:?}");
+                generator
+            }
+            Err(err) => {
+                warn!("Failed to load world generation config from {path:?}, using the default one: {err:?}");
+                NoiseGenerator { seed, config: WorldGenConfig::default() }
+            }
+        };
+        MapGenerationPlugin {
+            config_file: Some((path, seed)),
+            ..Self::with_generator(generator, save_directory)
+        }
+    }
 }
 
 impl Plugin for MapGenerationPlugin {
     fn build(&self, app: &mut App) {
+        if let Some(config) = self.generator.get_config() {
+            app.insert_resource(config.clone());
+        }
+        if let Some((path, seed)) = &self.config_file {
+            app.add_plugins(ConfigReloadPlugin { path: path.clone(), seed: *seed });
+        }
         app
-            .insert_resource(Generator { seed: self.seed })
-            .add_systems(Update, chunk_spawner)
-            .add_systems(Update, chunk_despawner);
+            .add_plugins(ChunkInteractionPlugin)
+            .add_plugins(VoxelWorldPlugin)
+            .add_plugins(LightingPlugin)
+            .add_plugins(FarTerrainPlugin)
+            .insert_resource(ActiveWorldGenerator(self.generator.clone()))
+            .insert_resource(ChunkStorage::new(self.save_directory.clone()))
+            .init_resource::<GeneratingColumns>()
+            .add_systems(Update, (chunk_spawner, collect_generated_columns).chain())
+            .add_systems(Update, chunk_despawner)
+            .add_systems(Last, save_on_exit);
     }
 }
 
-fn chunk_spawner(map: Res<Generator>,
+/// Columns being loaded or generated on the [`AsyncComputeTaskPool`].
+#[derive(Resource, Default)]
+struct GeneratingColumns(HashMap<(i32, i32), Task<Vec<Chunk>>>);
+
+fn chunk_spawner(generator: Res<ActiveWorldGenerator>,
+                 storage: Res<ChunkStorage>,
+                 registry: Res<BlockRegistry>,
                  cameras: Query<(&Transform, &Camera)>,
-                 mut commands: Commands,
-                 query: Query<&ChunkPos>,)
+                 index: Res<ChunkIndex>,
+                 mut generating: ResMut<GeneratingColumns>,)
 {
-    for (tr, _) in &cameras {
-        use std::time::Instant;
-        let now = Instant::now();
-
-        let ch_x = (tr.translation.x / (CHUNK_SIZE_F32 * CUBE_SIDE)).floor() as i32;
-        let ch_z = (tr.translation.z / (CHUNK_SIZE_F32 * CUBE_SIDE)).floor() as i32;
-        let chunks = get_visible_chunks(ch_x, ch_z);
-        let new_chunks: Vec<_> = chunks.iter()
-            .filter(|(x, z)| !query.iter().any(|c| c.0.x == *x && c.0.z == *z))
-            .collect();
-        if new_chunks.len() == 0 {
-            return;
+    let camera_chunks: Vec<_> = cameras.iter()
+        .map(|(tr, _)| ChunkPos::new(
+            (tr.translation.x / (CHUNK_SIZE_F32 * CUBE_SIDE)).floor() as i32,
+            0,
+            (tr.translation.z / (CHUNK_SIZE_F32 * CUBE_SIDE)).floor() as i32))
+        .collect();
+    let visible_chunks: HashSet<_> = camera_chunks.iter()
+        .flat_map(|pos| get_visible_chunks(pos.0.x, pos.0.z))
+        .collect();
+
+    // Dropping a task cancels it if it hasn't started yet.
+    generating.0.retain(|&(x, z), _| {
+        let is_visible = visible_chunks.contains(&(x, z));
+        if !is_visible {
+            info!("Cancelling generation of chunk ({x}, {z})");
+        }
+        is_visible
+    });
+
+    let free_slots = MAX_GENERATING_COLUMNS.saturating_sub(generating.0.len());
+    if free_slots == 0 {
+        return;
+    }
+
+    let mut new_chunks: Vec<_> = visible_chunks.into_iter()
+        .filter(|&(x, z)| !index.contains(&ChunkPos::new(x, 0, z)) && !generating.0.contains_key(&(x, z)))
+        .collect();
+    new_chunks.sort_by_key(|&(x, z)| camera_chunks.iter()
+        .map(|pos| pos.planar_distance(&ChunkPos::new(x, 0, z)))
+        .min());
+
+    let pool = AsyncComputeTaskPool::get();
+    for (ch_x, ch_z) in new_chunks.into_iter().take(free_slots) {
+        info!("Spawning chunk ({ch_x}, {ch_z})");
+        let generator = generator.0.clone();
+        let storage = storage.clone();
+        let registry = registry.clone();
+        let task = pool.spawn(async move {
+            load_or_generate_column(ch_x, ch_z, generator.as_ref(), &storage, &registry)
+        });
+        generating.0.insert((ch_x, ch_z), task);
+    }
+}
+
+fn collect_generated_columns(mut generating: ResMut<GeneratingColumns>,
+                             mut commands: Commands,) {
+    let finished: Vec<_> = generating.0.iter()
+        .filter(|(_, task)| task.is_finished())
+        .map(|(&column, _)| column)
+        .collect();
+
+    for (ch_x, ch_z) in finished {
+        let task = generating.0.remove(&(ch_x, ch_z)).unwrap();
+        let column = block_on(task);
+        for (ch_y, chunk) in column.into_iter().enumerate() {
+            let chunk_coordinates = ChunkPos::new(ch_x, ch_y as i32, ch_z);
+            commands.spawn((chunk, chunk_coordinates));
         }
-        let &(nch_x, nch_z) = new_chunks[0];
-        info!("Spawning chunk ({nch_x}, {nch_z})");
-        spawn_chunk_column(nch_x, nch_z, &map, &mut commands);
-        let total = now.elapsed();
-        info!("Chunk ({nch_x}, {nch_z}) spawned. Total time: {total:.2?}.");
+        info!("Chunk ({ch_x}, {ch_z}) spawned.");
     }
 }
 
@@ -71,7 +186,8 @@ fn get_visible_chunks(ch_x: i32, ch_z: i32) -> Vec<(i32, i32)> {
 }
 
 fn chunk_despawner(cameras: Query<(&Transform, &Camera)>,
-                   query: Query<(Entity, &ChunkPos)>,
+                   query: Query<(Entity, &ChunkPos, &Chunk)>,
+                   storage: Res<ChunkStorage>,
                    mut commands: Commands,) {
     let mut pos = (&cameras).iter()
         .map(|x| x.0.translation)
@@ -79,20 +195,73 @@ fn chunk_despawner(cameras: Query<(&Transform, &Camera)>,
         / (CHUNK_SIZE_F32 * CUBE_SIDE);
     pos = Vec3::new(pos.x.round(), pos.y.round(), pos.z.round());
 
-    for (entity, &coords/*, handle*/) in &query {
+    let mut despawned_columns = HashMap::new();
+    for (entity, &coords, chunk) in &query {
         if coords.planar_distance(&ChunkPos::new(pos.x as i32, 0, pos.z as i32)) > VISIBLE_CHUNKS_DISTANCE as i32 * 3
             || coords.planar_distance(&ChunkPos::new(pos.x as i32, 0, pos.z as i32)) < CHUNKS_CUT_DISTANCE as i32 {
             info!("Despawning chunk ({}, {})", coords.0.x, coords.0.z);
+            despawned_columns
+                .entry((coords.0.x, coords.0.z))
+                .or_insert_with(Vec::new)
+                .push((coords.0.y, chunk));
             commands
                 .entity(entity)
                 .despawn();
         }
     }
+
+    save_modified_columns(despawned_columns, &storage);
+}
+
+fn save_on_exit(mut exit: EventReader<AppExit>,
+                mut query: Query<(&ChunkPos, &mut Chunk)>,
+                storage: Res<ChunkStorage>,) {
+    if exit.read().next().is_none() {
+        return;
+    }
+
+    let mut columns = HashMap::new();
+    for (coords, chunk) in &query {
+        columns
+            .entry((coords.0.x, coords.0.z))
+            .or_insert_with(Vec::new)
+            .push((coords.0.y, chunk));
+    }
+    save_modified_columns(columns, &storage);
+
+    for (_, mut chunk) in &mut query {
+        chunk.is_modified = false;
+    }
 }
 
-fn spawn_chunk_column(ch_x: i32, ch_z: i32, map: &Generator, commands: &mut Commands) {
-    for (ch_y, chunk) in map.get_chunk_column(ch_x, ch_z).iter().enumerate() {
-        let chunk_coordinates = ChunkPos::new(ch_x, ch_y as i32, ch_z);
-        commands.spawn((chunk.clone(), chunk_coordinates));
+/// Saves every column that has at least one modified chunk.
+fn save_modified_columns(columns: HashMap<(i32, i32), Vec<(i32, &Chunk)>>, storage: &ChunkStorage) {
+    for ((ch_x, ch_z), mut chunks) in columns {
+        if !chunks.iter().any(|(_, chunk)| chunk.is_modified) {
+            continue;
+        }
+
+        chunks.sort_by_key(|(ch_y, _)| *ch_y);
+        let chunks: Vec<_> = chunks.into_iter().map(|(_, chunk)| chunk).collect();
+        match storage.save_column(ch_x, ch_z, &chunks) {
+            Ok(()) => info!("Chunk ({ch_x}, {ch_z}) saved"),
+            Err(err) => error!("Failed to save chunk ({ch_x}, {ch_z}): {err:?}"),
+        }
     }
 }
+
+fn load_or_generate_column(ch_x: i32, ch_z: i32,
+                           generator: &dyn WorldGenerator,
+                           storage: &ChunkStorage,
+                           registry: &BlockRegistry) -> Vec<Chunk> {
+    let mut column = match storage.load_column(ch_x, ch_z, registry) {
+        Ok(Some(column)) => column,
+        Ok(None) => generator.get_chunk_column(ch_x, ch_z),
+        Err(err) => {
+            error!("Failed to load chunk ({ch_x}, {ch_z}), generating it instead: {err:?}");
+            generator.get_chunk_column(ch_x, ch_z)
+        }
+    };
+    light_column(ch_x, ch_z, &mut column);
+    column
+}
@@ -0,0 +1,126 @@
+use crate::map::chunk::{BlockRegistry, Chunk, PaletteStorage};
+use super::PersistenceError;
+
+/// Palette entry of an empty cell. No block has the id `u16::MAX`, so no raw block is equal to it.
+const AIR: u32 = u32::MAX;
+
+/// Serializes a chunk column bottom to top.
+///
+/// Every chunk is written as its palette (`u32` length and raw blocks as `u32`),
+/// index width `u8` and packed indices (`u32` length and `u64` words).
+pub fn encode_column(chunks: &[&Chunk]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.push(chunks.len() as u8);
+    for chunk in chunks {
+        let storage = chunk.storage();
+        out.extend_from_slice(&(storage.palette().len() as u32).to_le_bytes());
+        for block in storage.palette() {
+            let raw = block.map_or(AIR, |b| b.to_raw());
+            out.extend_from_slice(&raw.to_le_bytes());
+        }
+        out.push(storage.bits() as u8);
+        out.extend_from_slice(&(storage.data().len() as u32).to_le_bytes());
+        for word in storage.data() {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    out
+}
+
+pub fn decode_column(bytes: &[u8], registry: &BlockRegistry) -> Result<Vec<Chunk>, PersistenceError> {
+    let mut reader = ByteReader(bytes);
+    let count = reader.read::<1>()?[0];
+    let mut chunks = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let palette_len = u32::from_le_bytes(reader.read()?);
+        let mut palette = Vec::new();
+        for _ in 0..palette_len {
+            let raw = u32::from_le_bytes(reader.read()?);
+            if raw == AIR {
+                palette.push(None);
+                continue;
+            }
+            let block = registry.get_block_from_raw(raw).ok_or(PersistenceError::UnknownBlock(raw))?;
+            palette.push(Some(block));
+        }
+
+        let bits = reader.read::<1>()?[0] as usize;
+        let words = u32::from_le_bytes(reader.read()?);
+        let mut data = Vec::new();
+        for _ in 0..words {
+            data.push(u64::from_le_bytes(reader.read()?));
+        }
+
+        let storage = PaletteStorage::from_parts(palette, bits, data, Chunk::SIZE)
+            .ok_or_else(|| PersistenceError::InvalidFormat("Inconsistent chunk storage".to_string()))?;
+        let chunk = Chunk::from_storage(storage)
+            .map_err(|_| PersistenceError::InvalidFormat("Invalid chunk size".to_string()))?;
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl ByteReader<'_> {
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], PersistenceError> {
+        if self.0.len() < N {
+            return Err(PersistenceError::InvalidFormat("Unexpected end of column data".to_string()));
+        }
+        let (head, tail) = self.0.split_at(N);
+        self.0 = tail;
+        Ok(head.try_into().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::chunk::{Block, BlockType};
+    use crate::utils::BlockPos;
+
+    fn column(registry: &BlockRegistry) -> Vec<Chunk> {
+        let door = registry.get_by_name("door").unwrap();
+        let mut bottom = Chunk::new();
+        let mut top = Chunk::new();
+        for i in 0..Chunk::SIZE {
+            let pos = BlockPos::from_index(i);
+            match i % 7 {
+                0 => bottom.set_block(&pos, &BlockType::STONE).unwrap(),
+                1 => bottom.set_block(&pos, &BlockType::WATER).unwrap(),
+                2 => bottom.replace_block(&pos, Some(Block::from(door).with_state("open", 1).unwrap())).unwrap(),
+                _ => None,
+            };
+        }
+        top.set_block(&BlockPos::new(3, 4, 5), door).unwrap();
+        vec![bottom, Chunk::new(), top]
+    }
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::new();
+        registry.load_definitions_str(r##"[(id: 300, name: "Door", color: "#885522", states: [(name: "open", values: 2)])]"##).unwrap();
+        registry
+    }
+
+    #[test]
+    fn columns_survive_round_trip() {
+        let registry = registry();
+        let column = column(&registry);
+        let bytes = encode_column(&column.iter().collect::<Vec<_>>());
+        let decoded = decode_column(&bytes, &registry).unwrap();
+
+        assert_eq!(decoded.len(), column.len());
+        for (chunk, decoded) in column.iter().zip(&decoded) {
+            assert_eq!(decoded.get_amount_of_blocks(), chunk.get_amount_of_blocks());
+            assert!(chunk.iter().eq(decoded.iter()));
+        }
+    }
+
+    #[test]
+    fn unknown_blocks_and_truncated_data_are_errors() {
+        let column = column(&registry());
+        let bytes = encode_column(&column.iter().collect::<Vec<_>>());
+        assert!(matches!(decode_column(&bytes, &BlockRegistry::new()), Err(PersistenceError::UnknownBlock(_))));
+        assert!(matches!(decode_column(&bytes[..bytes.len() - 1], &registry()), Err(PersistenceError::InvalidFormat(_))));
+    }
+}
@@ -0,0 +1,68 @@
+mod chunk_codec;
+mod region_file;
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use bevy::prelude::*;
+use crate::map::chunk::{BlockRegistry, Chunk};
+pub use region_file::{Compression, RegionFile, REGION_FORMAT_VERSION, REGION_SIZE};
+
+#[derive(Debug)]
+pub enum PersistenceError {
+    Io(std::io::Error),
+    InvalidFormat(String),
+    UnsupportedVersion(u16),
+    UnknownBlock(u32),
+}
+
+impl From<std::io::Error> for PersistenceError {
+    fn from(value: std::io::Error) -> Self {
+        PersistenceError::Io(value)
+    }
+}
+
+/// Saves chunk columns to region files in `directory` and loads them back.
+#[derive(Resource, Clone)]
+pub struct ChunkStorage {
+    directory: PathBuf,
+    compression: Compression,
+    /// Region files are accessed from several systems and tasks.
+    lock: Arc<Mutex<()>>,
+}
+
+impl ChunkStorage {
+    pub fn new(directory: PathBuf) -> Self {
+        ChunkStorage {
+            directory,
+            compression: Compression::Zlib,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Column saved at the given position, `None` if it was never saved.
+    pub fn load_column(&self, ch_x: i32, ch_z: i32, registry: &BlockRegistry) -> Result<Option<Vec<Chunk>>, PersistenceError> {
+        let ((r_x, r_z), index) = RegionFile::locate(ch_x, ch_z);
+        let _guard = self.lock.lock().unwrap();
+        let Some(mut region) = RegionFile::open(&self.region_path(r_x, r_z))? else {
+            return Ok(None);
+        };
+        match region.read_column(index)? {
+            Some(bytes) => Ok(Some(chunk_codec::decode_column(&bytes, registry)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Saves chunks of a column ordered bottom to top.
+    pub fn save_column(&self, ch_x: i32, ch_z: i32, chunks: &[&Chunk]) -> Result<(), PersistenceError> {
+        let ((r_x, r_z), index) = RegionFile::locate(ch_x, ch_z);
+        let bytes = chunk_codec::encode_column(chunks);
+        let _guard = self.lock.lock().unwrap();
+        std::fs::create_dir_all(&self.directory)?;
+        let mut region = RegionFile::open_or_create(&self.region_path(r_x, r_z), self.compression)?;
+        region.write_column(index, &bytes)
+    }
+
+    fn region_path(&self, r_x: i32, r_z: i32) -> PathBuf {
+        self.directory.join(format!("r.{r_x}.{r_z}.yamc"))
+    }
+}
@@ -0,0 +1,268 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use super::PersistenceError;
+
+/// Number of chunk columns along one side of a region.
+pub const REGION_SIZE: i32 = 32;
+pub const REGION_FORMAT_VERSION: u16 = 1;
+
+const MAGIC: &[u8; 4] = b"YAMC";
+const COLUMNS_IN_REGION: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const ENTRY_SIZE: usize = 8;
+const PREAMBLE_SIZE: usize = 8;
+const HEADER_SIZE: usize = PREAMBLE_SIZE + COLUMNS_IN_REGION * ENTRY_SIZE;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Compression {
+    None = 0,
+    Zlib = 1,
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = PersistenceError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Zlib),
+            _ => Err(PersistenceError::InvalidFormat(format!("Unknown compression {value}"))),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Entry {
+    offset: u32,
+    length: u32,
+}
+
+/// File holding chunk columns of a `REGION_SIZE` x `REGION_SIZE` area.
+///
+/// Layout, all numbers little-endian:
+/// - `"YAMC"`, format version `u16`, compression `u8`, one reserved byte;
+/// - offset `u32` and length `u32` of every column, zero offset means the column is absent;
+/// - column data, compressed as stated in the header.
+pub struct RegionFile {
+    file: File,
+    compression: Compression,
+    entries: Vec<Entry>,
+}
+
+impl RegionFile {
+    pub fn open(path: &Path) -> Result<Option<Self>, PersistenceError> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut header = vec![0u8; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+        Ok(Some(Self::from_header(file, &header)?))
+    }
+
+    pub fn open_or_create(path: &Path, compression: Compression) -> Result<Self, PersistenceError> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        if file.metadata()?.len() == 0 {
+            let mut region = RegionFile {
+                file,
+                compression,
+                entries: vec![Entry::default(); COLUMNS_IN_REGION],
+            };
+            region.write_header()?;
+            return Ok(region);
+        }
+
+        let mut header = vec![0u8; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+        Self::from_header(file, &header)
+    }
+
+    /// Region coordinates and index inside the region of a chunk column.
+    pub fn locate(ch_x: i32, ch_z: i32) -> ((i32, i32), usize) {
+        let region = (ch_x.div_euclid(REGION_SIZE), ch_z.div_euclid(REGION_SIZE));
+        let index = (ch_z.rem_euclid(REGION_SIZE) * REGION_SIZE + ch_x.rem_euclid(REGION_SIZE)) as usize;
+        (region, index)
+    }
+
+    pub fn read_column(&mut self, index: usize) -> Result<Option<Vec<u8>>, PersistenceError> {
+        let entry = self.entries[index];
+        if entry.offset == 0 {
+            return Ok(None);
+        }
+
+        let mut data = vec![0u8; entry.length as usize];
+        self.file.seek(SeekFrom::Start(entry.offset as u64))?;
+        self.file.read_exact(&mut data)?;
+        Ok(Some(self.decompress(data)?))
+    }
+
+    /// Writes a column into the first gap between the columns it fits into, or after the last column.
+    /// Its previous slot is only freed once the new data is on disk, so a crash while writing keeps
+    /// the previous data readable. Space left past the last column is cut off.
+    pub fn write_column(&mut self, index: usize, data: &[u8]) -> Result<(), PersistenceError> {
+        let data = self.compress(data)?;
+        let offset = u32::try_from(self.allocate(data.len() as u64))
+            .map_err(|_| PersistenceError::InvalidFormat("Region file is too large".to_string()))?;
+
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(&data)?;
+        self.file.sync_data()?;
+        self.entries[index] = Entry { offset, length: data.len() as u32 };
+        self.write_entry(index)?;
+
+        let end = self.entries.iter()
+            .filter(|entry| entry.offset != 0)
+            .map(|entry| entry.offset as u64 + entry.length as u64)
+            .fold(HEADER_SIZE as u64, u64::max);
+        if self.file.metadata()?.len() > end {
+            self.file.set_len(end)?;
+        }
+        Ok(())
+    }
+
+    /// Offset of the first gap `length` bytes long between the columns.
+    fn allocate(&self, length: u64) -> u64 {
+        let mut used: Vec<_> = self.entries.iter()
+            .filter(|entry| entry.offset != 0)
+            .map(|entry| (entry.offset as u64, entry.offset as u64 + entry.length as u64))
+            .collect();
+        used.sort_unstable();
+
+        let mut start = HEADER_SIZE as u64;
+        for (offset, end) in used {
+            if offset >= start + length {
+                break;
+            }
+            start = start.max(end);
+        }
+        start
+    }
+
+    fn from_header(file: File, header: &[u8]) -> Result<Self, PersistenceError> {
+        if &header[0..4] != MAGIC {
+            return Err(PersistenceError::InvalidFormat("Not a region file".to_string()));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != REGION_FORMAT_VERSION {
+            return Err(PersistenceError::UnsupportedVersion(version));
+        }
+        let compression = Compression::try_from(header[6])?;
+
+        let entries = header[PREAMBLE_SIZE..]
+            .chunks_exact(ENTRY_SIZE)
+            .map(|e| Entry {
+                offset: u32::from_le_bytes([e[0], e[1], e[2], e[3]]),
+                length: u32::from_le_bytes([e[4], e[5], e[6], e[7]]),
+            })
+            .collect();
+
+        Ok(RegionFile { file, compression, entries })
+    }
+
+    fn write_header(&mut self) -> Result<(), PersistenceError> {
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&REGION_FORMAT_VERSION.to_le_bytes());
+        header.push(self.compression as u8);
+        header.push(0);
+        for entry in &self.entries {
+            header.extend_from_slice(&entry.offset.to_le_bytes());
+            header.extend_from_slice(&entry.length.to_le_bytes());
+        }
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        Ok(())
+    }
+
+    fn write_entry(&mut self, index: usize) -> Result<(), PersistenceError> {
+        let entry = self.entries[index];
+        let mut bytes = [0u8; ENTRY_SIZE];
+        bytes[0..4].copy_from_slice(&entry.offset.to_le_bytes());
+        bytes[4..8].copy_from_slice(&entry.length.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start((PREAMBLE_SIZE + index * ENTRY_SIZE) as u64))?;
+        self.file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+        match self.compression {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Zlib => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    fn decompress(&self, data: Vec<u8>) -> Result<Vec<u8>, PersistenceError> {
+        match self.compression {
+            Compression::None => Ok(data),
+            Compression::Zlib => {
+                let mut result = Vec::new();
+                flate2::read::ZlibDecoder::new(data.as_slice()).read_to_end(&mut result)?;
+                Ok(result)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("yamc-{}-{name}.yamc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn columns_survive_reopening() {
+        let path = temp_path("reopen");
+        let mut region = RegionFile::open_or_create(&path, Compression::Zlib).unwrap();
+        region.write_column(0, &[1; 500]).unwrap();
+        region.write_column(COLUMNS_IN_REGION - 1, &[2, 3, 4]).unwrap();
+        drop(region);
+
+        let mut region = RegionFile::open(&path).unwrap().unwrap();
+        assert_eq!(region.read_column(0).unwrap(), Some(vec![1; 500]));
+        assert_eq!(region.read_column(COLUMNS_IN_REGION - 1).unwrap(), Some(vec![2, 3, 4]));
+        assert_eq!(region.read_column(1).unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rewritten_columns_reuse_space() {
+        let path = temp_path("reuse");
+        let header = HEADER_SIZE as u64;
+        let mut region = RegionFile::open_or_create(&path, Compression::None).unwrap();
+        region.write_column(0, &[1; 100]).unwrap();
+        region.write_column(1, &[2; 100]).unwrap();
+        // Too large for its slot, moved past the other column.
+        region.write_column(0, &[3; 200]).unwrap();
+        assert_eq!(region.entries[0].offset as u64, header + 200);
+        // Fits into the slot left behind.
+        region.write_column(1, &[4; 50]).unwrap();
+        assert_eq!(region.entries[1].offset as u64, header);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), header + 400);
+        // Moves down next to the other column and the tail is cut off.
+        region.write_column(0, &[5; 10]).unwrap();
+        assert_eq!(region.entries[0].offset as u64, header + 50);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), header + 60);
+        // Never written over its own slot, the previous data stays until the entry points past it.
+        region.write_column(1, &[6; 50]).unwrap();
+        assert_eq!(region.entries[1].offset as u64, header + 60);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), header + 110);
+
+        drop(region);
+        let mut region = RegionFile::open(&path).unwrap().unwrap();
+        assert_eq!(region.read_column(0).unwrap(), Some(vec![5; 10]));
+        assert_eq!(region.read_column(1).unwrap(), Some(vec![6; 50]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
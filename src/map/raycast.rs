@@ -0,0 +1,163 @@
+use bevy::math::{IVec3, Ray3d, Vec3};
+use crate::map::chunk::Block;
+use crate::map::voxel_world::VoxelAccess;
+use crate::utils::{WorldBlockPos, WORLD_Y_OFFSET};
+
+/// Block hit by a ray.
+#[derive(Copy, Clone)]
+pub struct RaycastHit {
+    pub pos: WorldBlockPos,
+    pub block: Block,
+    /// Normal of the side the ray entered the block through.
+    pub normal: IVec3,
+    /// Distance from the ray origin to the hit side.
+    pub distance: f32,
+    /// Cell in front of the hit side, the ray passed through it right before the hit.
+    pub adjacent: WorldBlockPos,
+}
+
+/// First non-fluid block along `ray` within `max_distance`.
+pub fn raycast(world: &dyn VoxelAccess, ray: Ray3d, max_distance: f32) -> Option<RaycastHit> {
+    raycast_filtered(world, ray, max_distance, |block| !block.block_type.is_fluid)
+}
+
+/// First block along `ray` within `max_distance` accepted by `filter`.
+/// The block containing the ray origin is never hit, and `max_distance` must be finite.
+pub fn raycast_filtered(world: &dyn VoxelAccess,
+                        ray: Ray3d,
+                        max_distance: f32,
+                        filter: impl Fn(&Block) -> bool) -> Option<RaycastHit> {
+    debug_assert!(max_distance.is_finite(), "A ray of {max_distance} blocks never ends without a hit");
+    let direction = *ray.direction;
+    // Ray origin in block space, where block cells span [pos, pos + 1).
+    let origin = ray.origin + Vec3::new(0.5, WORLD_Y_OFFSET + 0.5, 0.5);
+    let mut cell = origin.floor().as_ivec3();
+
+    let step = direction.signum().as_ivec3();
+    // Distance along the ray to the next cell boundary and between boundaries on each axis.
+    let mut next_boundary = Vec3::INFINITY;
+    let mut boundary_step = Vec3::INFINITY;
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            continue;
+        }
+        let boundary = if step[axis] > 0 { cell[axis] + 1 } else { cell[axis] } as f32;
+        next_boundary[axis] = (boundary - origin[axis]) / direction[axis];
+        boundary_step[axis] = 1.0 / direction[axis].abs();
+    }
+
+    loop {
+        let axis = if next_boundary.x < next_boundary.y && next_boundary.x < next_boundary.z { 0 }
+            else if next_boundary.y < next_boundary.z { 1 }
+            else { 2 };
+        let distance = next_boundary[axis];
+        if distance > max_distance {
+            return None;
+        }
+
+        let previous = cell;
+        cell[axis] += step[axis];
+        next_boundary[axis] += boundary_step[axis];
+
+        let pos = WorldBlockPos(cell);
+        if let Some(block) = world.get_block(&pos).filter(&filter) {
+            let mut normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+            return Some(RaycastHit {
+                pos,
+                block,
+                normal,
+                distance,
+                adjacent: WorldBlockPos(previous),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use bevy::math::Dir3;
+    use crate::map::chunk::{BlockType, Chunk};
+    use crate::utils::{BlockPos, ChunkPos, WorldPos};
+    use super::*;
+
+    /// Chunks holding stone at every one of `blocks`.
+    fn create_chunks(blocks: &[IVec3]) -> HashMap<ChunkPos, Chunk> {
+        let mut chunks = HashMap::new();
+        for &block in blocks {
+            let pos = WorldBlockPos(block);
+            let chunk: &mut Chunk = chunks.entry(ChunkPos::from(pos)).or_insert_with(Chunk::new);
+            chunk.set_block(&BlockPos::from(pos), &BlockType::STONE).unwrap();
+        }
+        chunks
+    }
+
+    fn cast(chunks: &HashMap<ChunkPos, Chunk>, origin: WorldBlockPos, offset: Vec3, direction: Vec3) -> Option<RaycastHit> {
+        let world: HashMap<_, _> = chunks.iter().map(|(pos, chunk)| (*pos, chunk)).collect();
+        let ray = Ray3d::new(WorldPos::from(origin).0 + offset, Dir3::new(direction).unwrap());
+        raycast(&world, ray, 20.0)
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_facing_side() {
+        let y = 110;
+        let chunks = create_chunks(&[IVec3::new(5, y, 0), IVec3::new(0, y, -7)]);
+
+        let hit = cast(&chunks, WorldBlockPos(IVec3::new(0, y, 0)), Vec3::ZERO, Vec3::X).unwrap();
+        assert_eq!(hit.pos.0, IVec3::new(5, y, 0));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert_eq!(hit.adjacent.0, IVec3::new(4, y, 0));
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+
+        let hit = cast(&chunks, WorldBlockPos(IVec3::new(0, y, 0)), Vec3::ZERO, Vec3::NEG_Z).unwrap();
+        assert_eq!(hit.pos.0, IVec3::new(0, y, -7));
+        assert_eq!(hit.normal, IVec3::Z);
+
+        assert!(cast(&chunks, WorldBlockPos(IVec3::new(0, y, 0)), Vec3::ZERO, Vec3::Y).is_none());
+        assert!(cast(&chunks, WorldBlockPos(IVec3::new(-30, y, 0)), Vec3::ZERO, Vec3::X).is_none(), "out of reach");
+    }
+
+    #[test]
+    fn diagonal_rays_do_not_skip_corners() {
+        let y = 110;
+        // Only reachable through the corner shared with the origin block.
+        let chunks = create_chunks(&[IVec3::new(1, y, 1)]);
+        let hit = cast(&chunks, WorldBlockPos(IVec3::new(0, y, 0)), Vec3::ZERO, Vec3::new(1.0, 0.0, 1.0)).unwrap();
+        assert_eq!(hit.pos.0, IVec3::new(1, y, 1));
+        assert!((hit.distance - 0.5 * 2.0f32.sqrt()).abs() < 1e-5);
+
+        let wall: Vec<_> = (-2..12).flat_map(|dy| (-2..12).map(move |z| IVec3::new(6, y + dy, z))).collect();
+        let chunks = create_chunks(&wall);
+        let hit = cast(&chunks, WorldBlockPos(IVec3::new(0, y, 0)), Vec3::new(0.1, 0.2, 0.3), Vec3::ONE).unwrap();
+        assert_eq!(hit.pos.0.x, 6);
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 5.4 * 3.0f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn block_containing_the_origin_is_not_hit() {
+        let y = 110;
+        let chunks = create_chunks(&[IVec3::new(0, y, 0), IVec3::new(2, y, 0)]);
+        let hit = cast(&chunks, WorldBlockPos(IVec3::new(0, y, 0)), Vec3::new(0.2, 0.0, 0.0), Vec3::X).unwrap();
+        assert_eq!(hit.pos.0, IVec3::new(2, y, 0));
+        assert_eq!(hit.adjacent.0, IVec3::new(1, y, 0));
+    }
+
+    #[test]
+    fn hits_across_the_world_y_offset() {
+        // The block right below the origin of `WorldPos`, the ray starts above it.
+        let below_zero = WORLD_Y_OFFSET as i32 - 1;
+        let chunks = create_chunks(&[IVec3::new(3, below_zero, 0)]);
+        let ray = Ray3d::new(Vec3::new(3.0, 3.0, 0.0), Dir3::NEG_Y);
+        let world: HashMap<_, _> = chunks.iter().map(|(pos, chunk)| (*pos, chunk)).collect();
+        let hit = raycast(&world, ray, 20.0).unwrap();
+
+        assert_eq!(hit.pos.0, IVec3::new(3, below_zero, 0));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert_eq!(hit.adjacent.0, IVec3::new(3, below_zero + 1, 0));
+        assert!((hit.distance - 3.5).abs() < 1e-5);
+        let origin: WorldBlockPos = WorldPos(ray.origin).into();
+        assert_eq!(origin.0.y, WORLD_Y_OFFSET as i32 + 3);
+    }
+}
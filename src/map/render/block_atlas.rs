@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use bevy::asset::RenderAssetUsages;
+use bevy::image::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use crate::map::chunk::{BlockRegistry, BlockType};
+
+/// Directory with the block texture files.
+pub const BLOCK_TEXTURES_DIRECTORY: &str = "assets/textures/blocks";
+
+/// Texture with the faces of all registered blocks.
+#[derive(Resource, Clone)]
+pub struct BlockAtlas {
+    pub image: Handle<Image>,
+    pub layout: Arc<AtlasLayout>,
+}
+
+/// Where the block textures are in the atlas, in UV coordinates.
+#[derive(Default)]
+pub struct AtlasLayout {
+    /// Top, side and bottom textures by block type id.
+    faces: Vec<Option<[Rect; 3]>>,
+    /// White spot tinted by the color of blocks without textures.
+    blank: Rect,
+}
+
+impl AtlasLayout {
+    /// Texture of the block side facing `NEIGHBOUR_OFFSETS[side]`, `None` if the block is drawn with its color.
+    pub fn get_side_rect(&self, block_type: &BlockType, side: usize) -> Option<Rect> {
+        let faces = self.faces.get(block_type.id())?.as_ref()?;
+        Some(match side {
+            0 => faces[0],
+            1 => faces[2],
+            _ => faces[1],
+        })
+    }
+
+    pub fn blank(&self) -> Rect {
+        self.blank
+    }
+}
+
+pub(super) fn build_block_atlas(registry: Res<BlockRegistry>,
+                                mut images: ResMut<Assets<Image>>,
+                                mut commands: Commands,) {
+    let (image, layout) = create_atlas(&registry, Path::new(BLOCK_TEXTURES_DIRECTORY));
+    commands.insert_resource(BlockAtlas {
+        image: images.add(image),
+        layout: Arc::new(layout),
+    });
+}
+
+/// Packs the textures of all registered blocks into a grid of equal tiles, the first one blank.
+/// Blocks with missing or mismatched textures fall back to their color.
+fn create_atlas(registry: &BlockRegistry, directory: &Path) -> (Image, AtlasLayout) {
+    let mut files: Vec<&'static str> = registry.iter()
+        .filter_map(|block_type| block_type.textures)
+        .flat_map(|textures| [textures.top, textures.side, textures.bottom])
+        .collect();
+    files.sort();
+    files.dedup();
+
+    let mut tile_size = None;
+    let mut tiles = Vec::new();
+    for file in files {
+        match load_tile(&directory.join(file)) {
+            Ok(image) if tile_size.is_none_or(|size| size == image.size()) => {
+                tile_size = Some(image.size());
+                tiles.push((file, image));
+            }
+            Ok(image) => warn!("Block texture {file} is {} instead of {}, skipping it", image.size(), tile_size.unwrap()),
+            Err(err) => warn!("Failed to load block texture {file}: {err}"),
+        }
+    }
+
+    let tile_size = tile_size.unwrap_or(UVec2::ONE);
+    let columns = ((tiles.len() + 1) as f32).sqrt().ceil() as u32;
+    let rows = (tiles.len() as u32 + 1).div_ceil(columns);
+    let size = UVec2::new(columns, rows) * tile_size;
+    let tile_min = |tile: u32| UVec2::new(tile % columns, tile / columns) * tile_size;
+    let tile_rect = |tile: u32| Rect::from_corners(
+        tile_min(tile).as_vec2() / size.as_vec2(),
+        (tile_min(tile) + tile_size).as_vec2() / size.as_vec2());
+
+    let mut data = vec![u8::MAX; (size.x * size.y * 4) as usize];
+    let mut rects = HashMap::new();
+    let row_len = (tile_size.x * 4) as usize;
+    for (i, (file, image)) in tiles.iter().enumerate() {
+        let tile = i as u32 + 1;
+        let min = tile_min(tile);
+        let pixels = image.data.as_deref().unwrap_or_default();
+        for row in 0..tile_size.y {
+            let src = row as usize * row_len;
+            let dst = (((min.y + row) * size.x + min.x) * 4) as usize;
+            data[dst..dst + row_len].copy_from_slice(&pixels[src..src + row_len]);
+        }
+        rects.insert(*file, tile_rect(tile));
+    }
+
+    let mut faces = Vec::new();
+    for block_type in registry.iter() {
+        let Some(textures) = block_type.textures else {
+            continue;
+        };
+        let (Some(top), Some(side), Some(bottom)) = (rects.get(textures.top), rects.get(textures.side), rects.get(textures.bottom)) else {
+            continue;
+        };
+        if faces.len() <= block_type.id() {
+            faces.resize(block_type.id() + 1, None);
+        }
+        faces[block_type.id()] = Some([*top, *side, *bottom]);
+    }
+    info!("Built block atlas of {} textures", tiles.len());
+
+    let mut image = Image::new(
+        Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::nearest();
+    let layout = AtlasLayout {
+        faces,
+        blank: Rect::from_center_size(tile_rect(0).center(), Vec2::ZERO),
+    };
+    (image, layout)
+}
+
+fn load_tile(path: &Path) -> Result<Image, String> {
+    let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("png");
+    let image = Image::from_buffer(&bytes,
+                                   ImageType::Extension(extension),
+                                   CompressedImageFormats::NONE,
+                                   true,
+                                   ImageSampler::nearest(),
+                                   RenderAssetUsages::MAIN_WORLD)
+        .map_err(|err| err.to_string())?;
+    image.convert(TextureFormat::Rgba8UnormSrgb).ok_or_else(|| "unsupported pixel format".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_textures_are_packed_into_the_atlas() {
+        let mut registry = BlockRegistry::new();
+        registry.load_definitions(Path::new("assets/blocks.ron")).unwrap();
+        let (image, layout) = create_atlas(&registry, Path::new(BLOCK_TEXTURES_DIRECTORY));
+
+        // The blank tile and three textures in a 2x2 grid of 16x16 tiles.
+        assert_eq!(image.size(), UVec2::new(32, 32));
+
+        let brick = registry.get_by_name("brick").unwrap();
+        let brick_faces = [0, 1, 2].map(|side| layout.get_side_rect(brick, side).unwrap());
+        assert!(brick_faces.iter().all(|rect| *rect == brick_faces[0]));
+        assert_eq!(brick_faces[0].size(), Vec2::splat(0.5));
+        assert!(!brick_faces[0].contains(layout.blank().center()));
+
+        let log = registry.get_by_name("log").unwrap();
+        let (top, bottom, side) = (layout.get_side_rect(log, 0).unwrap(), layout.get_side_rect(log, 1).unwrap(), layout.get_side_rect(log, 2).unwrap());
+        assert_eq!(top, bottom);
+        assert_ne!(top, side);
+        assert!(layout.get_side_rect(registry.get_by_name("glass").unwrap(), 0).is_none());
+
+        // Tiles are copied pixel for pixel.
+        let tile = load_tile(&Path::new(BLOCK_TEXTURES_DIRECTORY).join("brick.png")).unwrap();
+        let min = (brick_faces[0].min * 32.0).as_uvec2();
+        let atlas_pixels = image.data.as_deref().unwrap();
+        let tile_pixels = tile.data.as_deref().unwrap();
+        for y in 0..16 {
+            let row = ((min.y + y) * 32 + min.x) as usize * 4;
+            assert_eq!(atlas_pixels[row..row + 16 * 4], tile_pixels[y as usize * 16 * 4..(y as usize + 1) * 16 * 4]);
+        }
+    }
+}
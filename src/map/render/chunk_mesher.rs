@@ -0,0 +1,62 @@
+use std::sync::Arc;
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::{Mesh, Resource};
+use bevy::render::mesh::PrimitiveTopology;
+use crate::map::chunk::Chunk;
+use crate::map::render::AtlasLayout;
+use crate::map::render::culled_chunk_mesher::CulledChunkMesher;
+use crate::map::render::greedy_chunk_mesher::GreedyChunkMesher;
+use crate::map::voxel_world::VoxelAccess;
+use crate::utils::ChunkPos;
+
+/// Strategy of turning chunk blocks into meshes.
+pub trait ChunkMesher: Send + Sync {
+    fn create_mesh(&self, chunk: &Chunk, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) -> ChunkMeshes;
+    fn update_mesh(&self, chunk: &Chunk, meshes: &mut ChunkMeshes, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout);
+}
+
+/// Meshes of a chunk. Blocks that aren't opaque are kept apart, to be drawn with alpha blending.
+pub struct ChunkMeshes {
+    pub opaque: Mesh,
+    pub translucent: Mesh,
+}
+
+impl ChunkMeshes {
+    pub fn new() -> Self {
+        let new_mesh = || Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD);
+        ChunkMeshes {
+            opaque: new_mesh(),
+            translucent: new_mesh(),
+        }
+    }
+}
+
+/// One quad per visible block side.
+pub struct CulledMesher;
+
+/// Visible sides of equal blocks merged into larger quads.
+pub struct GreedyMesher;
+
+impl ChunkMesher for CulledMesher {
+    fn create_mesh(&self, chunk: &Chunk, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) -> ChunkMeshes {
+        chunk.create_mesh_culled(pos, world, atlas)
+    }
+
+    fn update_mesh(&self, chunk: &Chunk, meshes: &mut ChunkMeshes, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) {
+        chunk.update_mesh_culled(meshes, pos, world, atlas)
+    }
+}
+
+impl ChunkMesher for GreedyMesher {
+    fn create_mesh(&self, chunk: &Chunk, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) -> ChunkMeshes {
+        chunk.create_mesh_greedy(pos, world, atlas)
+    }
+
+    fn update_mesh(&self, chunk: &Chunk, meshes: &mut ChunkMeshes, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) {
+        chunk.update_mesh_greedy(meshes, pos, world, atlas)
+    }
+}
+
+/// Mesher used for all chunks, chosen when [`super::StaticVoxelRenderPlugin`] is created.
+#[derive(Resource, Clone)]
+pub struct ActiveChunkMesher(pub Arc<dyn ChunkMesher>);
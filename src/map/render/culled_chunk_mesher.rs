@@ -1,15 +1,23 @@
-use std::collections::HashMap;
-use bevy::asset::RenderAssetUsages;
 use bevy::log::info;
-use bevy::math::{IVec3, Vec3};
+use bevy::math::{IVec3, Rect, Vec2, Vec3};
 use bevy::prelude::Mesh;
-use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
-use crate::map::chunk::Chunk;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use crate::map::chunk::{Block, BlockType, Chunk, Light};
+use crate::map::render::{AtlasLayout, ChunkMeshes};
+use crate::map::voxel_world::VoxelAccess;
 use crate::utils::{BlockPos, ChunkPos, WorldBlockPos};
 
 pub const VOXEL_HALF_SIDE: f32 = 0.5;
+/// How far the surface of a fluid is below the top of its block.
+pub(super) const FLUID_SURFACE_DROP: f32 = 0.125;
+/// Brightness of a side in complete darkness.
+const MIN_BRIGHTNESS: f32 = 0.05;
+/// Each light level below the maximum dims a side by this factor.
+const LIGHT_FALLOFF: f32 = 0.8;
+/// Brightness of a vertex by its ambient occlusion, from the most occluded corner to an open one.
+const AO_CURVE: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
 
-const SIDES_VERTICES: [[Vec3; 4]; 6] = [
+pub(super) const SIDES_VERTICES: [[Vec3; 4]; 6] = [
     [ // TOP
         Vec3::new(VOXEL_HALF_SIDE, VOXEL_HALF_SIDE, VOXEL_HALF_SIDE),
         Vec3::new(-VOXEL_HALF_SIDE, VOXEL_HALF_SIDE, VOXEL_HALF_SIDE),
@@ -48,34 +56,32 @@ const SIDES_VERTICES: [[Vec3; 4]; 6] = [
     ],
 ];
 
-const SIDES_INDICES: [u32; 6] = [2, 1, 0, 3, 2, 0];
-const SIDES_OFFSETS: [(i32, i32, i32); 6] = [(0, 1, 0), (0, -1, 0), (1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)];
+pub(super) const SIDES_INDICES: [u32; 6] = [2, 1, 0, 3, 2, 0];
+/// Same quad split along the other diagonal.
+pub(super) const FLIPPED_SIDES_INDICES: [u32; 6] = [3, 1, 0, 3, 2, 1];
+pub(super) const SIDES_OFFSETS: [(i32, i32, i32); 6] = [(0, 1, 0), (0, -1, 0), (1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)];
 
 pub trait CulledChunkMesher {
-    fn create_mesh_culled(&self, pos: &ChunkPos, map: &HashMap<ChunkPos, &Chunk>) -> Mesh;
-    fn update_mesh_culled(&self, mesh: &mut Mesh, pos: &ChunkPos, map: &HashMap<ChunkPos, &Chunk>);
+    fn create_mesh_culled(&self, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) -> ChunkMeshes;
+    fn update_mesh_culled(&self, meshes: &mut ChunkMeshes, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout);
 }
 
 impl CulledChunkMesher for Chunk {
-    fn create_mesh_culled(&self, pos: &ChunkPos, map: &HashMap<ChunkPos, &Chunk>) -> Mesh {
-        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD);
-        self.update_mesh_culled(&mut mesh, pos, map);
-        mesh
+    fn create_mesh_culled(&self, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) -> ChunkMeshes {
+        let mut meshes = ChunkMeshes::new();
+        self.update_mesh_culled(&mut meshes, pos, world, atlas);
+        meshes
     }
 
-    fn update_mesh_culled(&self, mesh: &mut Mesh, chunk_pos: &ChunkPos, map: &HashMap<ChunkPos, &Chunk>) {
+    fn update_mesh_culled(&self, meshes: &mut ChunkMeshes, chunk_pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) {
         use std::time::Instant;
         let now = Instant::now();
-        let mut vertices: Vec<[f32; 3]> = Vec::new();
-        let mut indices: Vec<u32> = Vec::new();
-        let mut colors: Vec<[f32; 4]> = Vec::new();
-
-        let mut current_index: u32 = 0;
+        let mut opaque = MeshBuffers::default();
+        let mut translucent = MeshBuffers::default();
 
         let mut blocks_counter = 0;
 
-        for (block_pos, block) in self.iter_with_pos().filter(|(_, b)| b.is_some()) {
-            let starting_time = now.elapsed();
+        for (block_pos, block) in self.iter_with_pos().filter_map(|(pos, b)| Some((pos, (*b)?))) {
             blocks_counter += 1;
             let sides = SIDES_OFFSETS
                 .map(|(ox, oy, oz)| {
@@ -84,59 +90,219 @@ impl CulledChunkMesher for Chunk {
                 })
                 .into_iter()
                 .enumerate()
-                .filter(|(_, side_pos)| !has_in_map_at(side_pos, chunk_pos, self, map))
-                .map(|(i, _)| i);
-
-            for side in sides {
-                vertices.extend(
-                    SIDES_VERTICES[side].map(|v| [
-                        v.x + (block_pos.0.x as f32) * VOXEL_HALF_SIDE * 2.0,
-                        v.y + (block_pos.0.y as f32) * VOXEL_HALF_SIDE * 2.0,
-                        v.z + (block_pos.0.z as f32) * VOXEL_HALF_SIDE * 2.0,
-                    ])
-                );
-                indices.extend(SIDES_INDICES.map(|si| si + (current_index * 4)));
-                current_index += 1;
-                colors.extend([[
-                    block.unwrap().block_type.color.to_srgba().red,
-                    block.unwrap().block_type.color.to_srgba().green,
-                    block.unwrap().block_type.color.to_srgba().blue,
-                    1.0]; 4]);
+                .filter(|(_, side_pos)| is_side_visible(&block, side_pos, chunk_pos, self, world));
+
+            let buffers = if block.block_type.is_opaque { &mut opaque } else { &mut translucent };
+            let surface_drop = if is_fluid_surface(&block, &block_pos, chunk_pos, self, world) { FLUID_SURFACE_DROP } else { 0.0 };
+            for (side, side_pos) in sides {
+                let vertices = SIDES_VERTICES[side].map(|v| [
+                    v.x + (block_pos.0.x as f32) * VOXEL_HALF_SIDE * 2.0,
+                    v.y + (block_pos.0.y as f32) * VOXEL_HALF_SIDE * 2.0 - if v.y > 0.0 { surface_drop } else { 0.0 },
+                    v.z + (block_pos.0.z as f32) * VOXEL_HALF_SIDE * 2.0,
+                ]);
+                let ao = get_side_ao(&block_pos, side, chunk_pos, self, world);
+                let (color, rect) = get_side_texture(&block, side, atlas);
+                let light = get_light_in_map_at(&side_pos, chunk_pos, self, world);
+                buffers.add_quad(vertices, side, get_side_uvs(side, rect), get_vertex_colors(color, light, &ao), get_side_indices(&ao));
             }
         }
 
+        let sides_counter = opaque.len() + translucent.len();
+        opaque.apply_to(&mut meshes.opaque);
+        translucent.apply_to(&mut meshes.translucent);
+
+        let total = now.elapsed();
+        if blocks_counter > 0 {
+            info!("Chunk meshed ({blocks_counter} blocks, {sides_counter} sides). Total time: {total:.0?}.");
+        }
+    }
+}
+
+/// Vertex data of a mesh being built, one quad at a time.
+#[derive(Default)]
+pub(super) struct MeshBuffers {
+    vertices: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuffers {
+    /// Adds a quad facing `side`, `indices` are relative to its first vertex.
+    pub(super) fn add_quad(&mut self,
+                           vertices: [[f32; 3]; 4],
+                           side: usize,
+                           uvs: [[f32; 2]; 4],
+                           colors: [[f32; 4]; 4],
+                           indices: [u32; 6]) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(vertices);
+        self.normals.extend([get_side_normal(side); 4]);
+        self.uvs.extend(uvs);
+        self.colors.extend(colors);
+        self.indices.extend(indices.map(|i| i + first));
+    }
+
+    /// Number of quads added.
+    pub(super) fn len(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    /// Replaces the geometry of `mesh` with the added quads.
+    pub(super) fn apply_to(self, mesh: &mut Mesh) {
         mesh.remove_attribute(Mesh::ATTRIBUTE_POSITION);
+        mesh.remove_attribute(Mesh::ATTRIBUTE_NORMAL);
+        mesh.remove_attribute(Mesh::ATTRIBUTE_UV_0);
         mesh.remove_attribute(Mesh::ATTRIBUTE_COLOR);
         mesh.remove_indices();
 
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION,
-                              VertexAttributeValues::Float32x3(vertices));
+                              VertexAttributeValues::Float32x3(self.vertices));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL,
+                              VertexAttributeValues::Float32x3(self.normals));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0,
+                              VertexAttributeValues::Float32x2(self.uvs));
         mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR,
-                              VertexAttributeValues::Float32x4(colors));
-        mesh.insert_indices(Indices::U32(indices));
+                              VertexAttributeValues::Float32x4(self.colors));
+        mesh.insert_indices(Indices::U32(self.indices));
+    }
+}
 
-        let total = now.elapsed();
-        if (blocks_counter > 0) {
-            info!("Chunk meshed ({blocks_counter} blocks, {current_index} sides). Total time: {total:.0?}.");
+pub(super) fn get_block_color(block: &Block) -> [f32; 4] {
+    let color = block.block_type.color.to_srgba();
+    let alpha = if block.block_type.is_opaque { 1.0 } else { color.alpha };
+    [color.red, color.green, color.blue, alpha]
+}
+
+/// Tint and atlas texture of a block side. Textured sides are only faded by the block alpha,
+/// the others are tinted with the block color over a blank texture.
+pub(super) fn get_side_texture(block: &Block, side: usize, atlas: &AtlasLayout) -> ([f32; 4], Rect) {
+    match atlas.get_side_rect(block.block_type, side) {
+        Some(rect) => ([1.0, 1.0, 1.0, get_block_color(block)[3]], rect),
+        None => (get_block_color(block), atlas.blank()),
+    }
+}
+
+/// Side tint dimmed by the light in front of the side and the ambient occlusion of every vertex.
+pub(super) fn get_vertex_colors(color: [f32; 4], light: Light, ao: &[u8; 4]) -> [[f32; 4]; 4] {
+    let [red, green, blue, alpha] = color;
+    let light_brightness = MIN_BRIGHTNESS
+        + (1.0 - MIN_BRIGHTNESS) * LIGHT_FALLOFF.powi((BlockType::MAX_LIGHT - light.level()) as i32);
+    ao.map(|ao| {
+        let brightness = light_brightness * AO_CURVE[ao as usize];
+        [red * brightness, green * brightness, blue * brightness, alpha]
+    })
+}
+
+pub(super) fn get_side_normal(side: usize) -> [f32; 3] {
+    let (ox, oy, oz) = SIDES_OFFSETS[side];
+    [ox as f32, oy as f32, oz as f32]
+}
+
+/// Texture coordinates of the side vertices, so side textures are upright when seen from outside.
+pub(super) fn get_side_uvs(side: usize, rect: Rect) -> [[f32; 2]; 4] {
+    let normal = Vec3::from(get_side_normal(side));
+    let (right, down) = if normal.y != 0.0 {
+        (Vec3::X, Vec3::Z * normal.y)
+    } else {
+        (Vec3::Y.cross(normal), Vec3::NEG_Y)
+    };
+    SIDES_VERTICES[side].map(|vertex| {
+        let local = Vec2::new(vertex.dot(right), vertex.dot(down)) / (VOXEL_HALF_SIDE * 2.0) + 0.5;
+        (rect.min + rect.size() * local).to_array()
+    })
+}
+
+/// Ambient occlusion of the vertices of a side of the block, from 0 for a vertex
+/// in a corner to 3 for a vertex with no opaque blocks around.
+pub(super) fn get_side_ao(block_pos: &BlockPos,
+                          side: usize,
+                          chunk_pos: &ChunkPos,
+                          chunk: &Chunk,
+                          world: &dyn VoxelAccess,) -> [u8; 4] {
+    let (ox, oy, oz) = SIDES_OFFSETS[side];
+    let front = block_pos.0 + IVec3::new(ox, oy, oz);
+    get_ao(side, |offset| {
+        let pos = WorldBlockPos::from(chunk_pos, &BlockPos(front + offset));
+        get_in_map_at(&pos, chunk_pos, chunk, world).is_some_and(|block| block.block_type.is_opaque)
+    })
+}
+
+/// Ambient occlusion of the corners of a side, `is_occluding` tells whether the space at an offset
+/// from the one in front of the side is taken by an opaque block.
+pub(super) fn get_ao(side: usize, is_occluding: impl Fn(IVec3) -> bool) -> [u8; 4] {
+    let (ox, oy, _) = SIDES_OFFSETS[side];
+    let d = if ox != 0 { 0 } else if oy != 0 { 1 } else { 2 };
+    let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+
+    SIDES_VERTICES[side].map(|vertex| {
+        let corner = vertex.signum().as_ivec3();
+        let (mut side1, mut side2) = (IVec3::ZERO, IVec3::ZERO);
+        side1[u] = corner[u];
+        side2[v] = corner[v];
+        match (is_occluding(side1), is_occluding(side2)) {
+            (true, true) => 0,
+            (a, b) => 3 - a as u8 - b as u8 - is_occluding(side1 + side2) as u8,
         }
+    })
+}
+
+/// Splits the quad along the diagonal with brighter ends, so shading is the same in every direction.
+pub(super) fn get_side_indices(ao: &[u8; 4]) -> [u32; 6] {
+    if ao[0] + ao[2] < ao[1] + ao[3] {
+        FLIPPED_SIDES_INDICES
+    } else {
+        SIDES_INDICES
     }
 }
 
-fn has_in_map_at(pos: &WorldBlockPos,
+/// A side is visible unless it is covered by an opaque block or by a block of the same type.
+pub(super) fn is_side_visible(block: &Block,
+                   side_pos: &WorldBlockPos,
+                   current_chunk_pos: &ChunkPos,
+                   current_chunk: &Chunk,
+                   world: &dyn VoxelAccess,) -> bool {
+    !is_side_covered(block, get_in_map_at(side_pos, current_chunk_pos, current_chunk, world).as_ref())
+}
+
+/// Whether a side of `block` is hidden by the `neighbour` in front of it.
+pub(super) fn is_side_covered(block: &Block, neighbour: Option<&Block>) -> bool {
+    neighbour.is_some_and(|neighbour| neighbour.block_type.is_opaque || neighbour.block_type == block.block_type)
+}
+
+/// Whether the block is a fluid with no fluid above, its top is then lowered by [`FLUID_SURFACE_DROP`].
+pub(super) fn is_fluid_surface(block: &Block,
+                               block_pos: &BlockPos,
+                               chunk_pos: &ChunkPos,
+                               chunk: &Chunk,
+                               world: &dyn VoxelAccess,) -> bool {
+    let above = WorldBlockPos::from(chunk_pos, &BlockPos(block_pos.0 + IVec3::Y));
+    block.block_type.is_fluid
+        && !get_in_map_at(&above, chunk_pos, chunk, world).is_some_and(|above| above.block_type.is_fluid)
+}
+
+fn get_in_map_at(pos: &WorldBlockPos,
                  current_chunk_pos: &ChunkPos,
                  current_chunk: &Chunk,
-                 map: &HashMap<ChunkPos, &Chunk>,) -> bool {
-    let chunk_pos = pos.clone().into();
-    let block_pos = pos.clone().into();
+                 world: &dyn VoxelAccess,) -> Option<Block> {
+    let chunk_pos: ChunkPos = pos.clone().into();
     if (chunk_pos == *current_chunk_pos) {
-        return matches!(current_chunk.get_block_at(&block_pos), Ok(Some(_)));
+        let block_pos = pos.clone().into();
+        return current_chunk.get_block_at(&block_pos).ok().copied().flatten();
     }
 
-    let chunk_op = map.get(&chunk_pos);
-    if chunk_op.is_none() {
-        return false;
+    world.get_block(pos)
+}
+
+pub(super) fn get_light_in_map_at(pos: &WorldBlockPos,
+                                  current_chunk_pos: &ChunkPos,
+                                  current_chunk: &Chunk,
+                                  world: &dyn VoxelAccess,) -> Light {
+    let chunk_pos: ChunkPos = (*pos).into();
+    if chunk_pos == *current_chunk_pos {
+        return current_chunk.get_light_at(&(*pos).into()).unwrap();
     }
 
-    let chunk = chunk_op.unwrap();
-    matches!(chunk.get_block_at(&block_pos), Ok(Some(_)))
-}
\ No newline at end of file
+    world.get_light(pos)
+}
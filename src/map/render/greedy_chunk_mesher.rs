@@ -0,0 +1,178 @@
+use bevy::log::info;
+use bevy::math::IVec3;
+use crate::map::chunk::{Block, Chunk, Light};
+use crate::map::render::{AtlasLayout, ChunkMeshes};
+use crate::map::render::culled_chunk_mesher::*;
+use crate::map::voxel_world::VoxelAccess;
+use crate::utils::{BlockPos, ChunkPos, WorldBlockPos, CHUNK_SIZE, CHUNK_SIZE_I32};
+
+/// Merges coplanar visible sides of equal blocks into larger quads.
+pub trait GreedyChunkMesher {
+    fn create_mesh_greedy(&self, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) -> ChunkMeshes;
+    fn update_mesh_greedy(&self, meshes: &mut ChunkMeshes, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout);
+}
+
+impl GreedyChunkMesher for Chunk {
+    fn create_mesh_greedy(&self, pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) -> ChunkMeshes {
+        let mut meshes = ChunkMeshes::new();
+        self.update_mesh_greedy(&mut meshes, pos, world, atlas);
+        meshes
+    }
+
+    fn update_mesh_greedy(&self, meshes: &mut ChunkMeshes, chunk_pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) {
+        use std::time::Instant;
+        let now = Instant::now();
+        let mut opaque = MeshBuffers::default();
+        let mut translucent = MeshBuffers::default();
+
+        if self.get_amount_of_blocks() > 0 {
+            for side in 0..SIDES_OFFSETS.len() {
+                for (min, max, (block, light, ao, is_surface)) in get_side_quads(self, side, chunk_pos, world, atlas) {
+                    let surface_drop = if is_surface { FLUID_SURFACE_DROP } else { 0.0 };
+                    let vertices = SIDES_VERTICES[side].map(|v| [
+                        v.x + (if v.x > 0.0 { max.x } else { min.x }) as f32 * VOXEL_HALF_SIDE * 2.0,
+                        v.y + (if v.y > 0.0 { max.y } else { min.y }) as f32 * VOXEL_HALF_SIDE * 2.0
+                            - if v.y > 0.0 { surface_drop } else { 0.0 },
+                        v.z + (if v.z > 0.0 { max.z } else { min.z }) as f32 * VOXEL_HALF_SIDE * 2.0,
+                    ]);
+                    let (color, rect) = get_side_texture(&block, side, atlas);
+                    let buffers = if block.block_type.is_opaque { &mut opaque } else { &mut translucent };
+                    buffers.add_quad(vertices, side, get_side_uvs(side, rect), get_vertex_colors(color, light, &ao), get_side_indices(&ao));
+                }
+            }
+        }
+
+        let quads_counter = opaque.len() + translucent.len();
+        opaque.apply_to(&mut meshes.opaque);
+        translucent.apply_to(&mut meshes.translucent);
+
+        let total = now.elapsed();
+        if quads_counter > 0 {
+            info!("Chunk meshed greedily ({quads_counter} quads). Total time: {total:.0?}.");
+        }
+    }
+}
+
+/// Look of a single block side, with whether it belongs to a lowered fluid surface.
+type Face = (Block, Light, [u8; 4], bool);
+
+/// Merged quads facing `side` as inclusive ranges of block positions.
+/// Only untextured sides of equal blocks lit and occluded equally are merged,
+/// as a texture can't repeat over a quad.
+fn get_side_quads(chunk: &Chunk, side: usize, chunk_pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout) -> Vec<(IVec3, IVec3, Face)> {
+    let (ox, oy, oz) = SIDES_OFFSETS[side];
+    let normal = IVec3::new(ox, oy, oz);
+    // Axis along the side normal and the two axes of the side plane.
+    let d = if ox != 0 { 0 } else if oy != 0 { 1 } else { 2 };
+    let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+
+    let mut quads = Vec::new();
+    let mut mask: Vec<Option<Face>> = vec![None; CHUNK_SIZE * CHUNK_SIZE];
+    for slice in 0..CHUNK_SIZE_I32 {
+        for j in 0..CHUNK_SIZE_I32 {
+            for i in 0..CHUNK_SIZE_I32 {
+                let mut pos = IVec3::ZERO;
+                pos[d] = slice;
+                pos[u] = i;
+                pos[v] = j;
+                let block_pos = BlockPos(pos);
+                let Ok(Some(block)) = chunk.get_block_at(&block_pos) else {
+                    mask[(j * CHUNK_SIZE_I32 + i) as usize] = None;
+                    continue;
+                };
+
+                let side_pos = WorldBlockPos::from(chunk_pos, &BlockPos(pos + normal));
+                mask[(j * CHUNK_SIZE_I32 + i) as usize] = is_side_visible(block, &side_pos, chunk_pos, chunk, world)
+                    .then(|| (
+                        *block,
+                        get_light_in_map_at(&side_pos, chunk_pos, chunk, world),
+                        get_side_ao(&block_pos, side, chunk_pos, chunk, world),
+                        is_fluid_surface(block, &block_pos, chunk_pos, chunk, world),
+                    ));
+            }
+        }
+
+        for j in 0..CHUNK_SIZE {
+            let mut i = 0;
+            while i < CHUNK_SIZE {
+                let Some(face) = mask[j * CHUNK_SIZE + i] else {
+                    i += 1;
+                    continue;
+                };
+
+                let is_mergeable = atlas.get_side_rect(face.0.block_type, side).is_none();
+                let mut width = 1;
+                while is_mergeable && i + width < CHUNK_SIZE && mask[j * CHUNK_SIZE + i + width] == Some(face) {
+                    width += 1;
+                }
+
+                let mut height = 1;
+                while is_mergeable && j + height < CHUNK_SIZE
+                    && (i..i + width).all(|k| mask[(j + height) * CHUNK_SIZE + k] == Some(face)) {
+                    height += 1;
+                }
+
+                for h in 0..height {
+                    mask[(j + h) * CHUNK_SIZE + i..(j + h) * CHUNK_SIZE + i + width].fill(None);
+                }
+
+                let mut min = IVec3::ZERO;
+                min[d] = slice;
+                min[u] = i as i32;
+                min[v] = j as i32;
+                let mut max = min;
+                max[u] += width as i32 - 1;
+                max[v] += height as i32 - 1;
+                quads.push((min, max, face));
+
+                i += width;
+            }
+        }
+    }
+    quads
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::map::chunk::BlockType;
+    use crate::map::generator::{NoiseGenerator, WorldGenConfig, WorldGenerator};
+    use crate::map::lighting::light_column;
+    use crate::map::render::{ChunkMesher, CulledMesher, GreedyMesher};
+    use super::*;
+
+    fn count_vertices(mesher: &dyn ChunkMesher, chunks: &HashMap<ChunkPos, &Chunk>) -> usize {
+        chunks.iter()
+            .map(|(pos, chunk)| mesher.create_mesh(chunk, pos, chunks, &AtlasLayout::default()))
+            .map(|meshes| meshes.opaque.count_vertices() + meshes.translucent.count_vertices())
+            .sum()
+    }
+
+    #[test]
+    fn greedy_meshes_of_generated_terrain_have_fewer_vertices() {
+        let generator = NoiseGenerator { seed: 1337, config: WorldGenConfig::default() };
+        let mut column = generator.get_chunk_column(0, 0);
+        light_column(0, 0, &mut column);
+        let chunks: HashMap<_, _> = column.iter()
+            .enumerate()
+            .map(|(ch_y, chunk)| (ChunkPos::new(0, ch_y as i32, 0), chunk))
+            .collect();
+
+        let culled = count_vertices(&CulledMesher, &chunks);
+        let greedy = count_vertices(&GreedyMesher, &chunks);
+        assert!(culled > 0);
+        assert!(greedy < culled, "greedy {greedy} vertices, culled {culled}");
+    }
+
+    #[test]
+    fn solid_chunk_is_meshed_as_its_six_sides() {
+        let mut chunk = Chunk::new();
+        for i in 0..Chunk::SIZE {
+            chunk.set_block(&BlockPos::from_index(i), &BlockType::STONE).unwrap();
+        }
+        let chunks = HashMap::from([(ChunkPos::new(0, 0, 0), &chunk)]);
+
+        assert_eq!(count_vertices(&GreedyMesher, &chunks), 6 * 4);
+        assert_eq!(count_vertices(&CulledMesher, &chunks), 6 * CHUNK_SIZE * CHUNK_SIZE * 4);
+    }
+}
@@ -0,0 +1,272 @@
+use bevy::log::info;
+use bevy::math::IVec3;
+use bevy::prelude::Component;
+use crate::map::chunk::{Block, Chunk, Light};
+use crate::map::render::{AtlasLayout, ChunkMeshes};
+use crate::map::render::culled_chunk_mesher::*;
+use crate::map::voxel_world::VoxelAccess;
+use crate::utils::{BlockPos, ChunkPos, WorldBlockPos, CHUNK_SIZE_I32, NEIGHBOUR_OFFSETS};
+
+/// Planar distances in chunks from the camera at which chunks switch to the next level of detail.
+const LOD_DISTANCES: [i32; 3] = [3, 6, 10];
+
+/// Level of detail a chunk is meshed at.
+#[derive(Component, Copy, Clone, PartialEq, Eq, Debug)]
+pub(super) struct ChunkLod {
+    /// Blocks are merged into cells of `2^level` blocks on each axis.
+    pub level: u32,
+    /// Sides bordering chunks meshed at other levels, bits in [`NEIGHBOUR_OFFSETS`] order.
+    pub seams: u8,
+}
+
+impl ChunkLod {
+    /// Level of detail of the chunk at `pos` when the camera is in the chunk at `center`.
+    pub fn at(pos: &ChunkPos, center: &ChunkPos) -> Self {
+        let level = get_lod_level(pos, center);
+        let seams = NEIGHBOUR_OFFSETS.iter()
+            .enumerate()
+            .filter(|(_, offset)| get_lod_level(&ChunkPos(pos.0 + **offset), center) != level)
+            .fold(0, |seams, (side, _)| seams | 1 << side);
+        ChunkLod { level, seams }
+    }
+
+    /// Side of the cells blocks are merged into.
+    pub fn scale(&self) -> i32 {
+        1 << self.level
+    }
+}
+
+fn get_lod_level(pos: &ChunkPos, center: &ChunkPos) -> u32 {
+    let distance = pos.planar_distance(center);
+    LOD_DISTANCES.iter().filter(|&&lod_distance| distance >= lod_distance).count() as u32
+}
+
+/// World seen by a chunk being meshed. Chunks across its seams are hidden, so the sides
+/// facing them are kept and no gaps open whatever the neighbour meshes look like.
+pub(super) struct SeamedWorld<'a> {
+    pub world: &'a dyn VoxelAccess,
+    pub chunk_pos: ChunkPos,
+    pub seams: u8,
+}
+
+impl VoxelAccess for SeamedWorld<'_> {
+    fn get_chunk(&self, pos: &ChunkPos) -> Option<&Chunk> {
+        let offset = pos.0 - self.chunk_pos.0;
+        let is_hidden = NEIGHBOUR_OFFSETS.iter()
+            .enumerate()
+            .any(|(side, side_offset)| self.seams & (1 << side) != 0 && side_offset.dot(offset) > 0);
+        if is_hidden {
+            return None;
+        }
+        self.world.get_chunk(pos)
+    }
+
+    /// Hidden chunks still light the sides facing them.
+    fn get_light(&self, pos: &WorldBlockPos) -> Light {
+        self.world.get_light(pos)
+    }
+}
+
+/// Meshes the chunk from cells of `lod.scale()` blocks on each axis, one quad per visible cell side.
+/// A cell is filled when at least half of its blocks are present, with the most common block of its top layer.
+pub(super) fn create_lod_mesh(chunk_pos: &ChunkPos, world: &dyn VoxelAccess, atlas: &AtlasLayout, lod: ChunkLod) -> ChunkMeshes {
+    use std::time::Instant;
+    let now = Instant::now();
+    let mut meshes = ChunkMeshes::new();
+    let mut opaque = MeshBuffers::default();
+    let mut translucent = MeshBuffers::default();
+
+    let world = SeamedWorld { world, chunk_pos: *chunk_pos, seams: lod.seams };
+    let scale = lod.scale();
+    let cells = CHUNK_SIZE_I32 / scale;
+    let chunk_min = WorldBlockPos::from(chunk_pos, &BlockPos::new(0, 0, 0));
+
+    // Cells of the chunk and a border of cells around it, sampled once for visibility and ambient occlusion.
+    let side = cells + 2;
+    let to_cell = |index: i32| IVec3::new(index % side, index / side % side, index / (side * side)) - 1;
+    let is_empty = world.get_chunk(chunk_pos).is_none_or(|chunk| chunk.get_amount_of_blocks() == 0);
+    let sampled: Vec<_> = if is_empty {
+        Vec::new()
+    } else {
+        (0..side * side * side)
+            .map(|index| sample_cell(&world, WorldBlockPos(chunk_min.0 + to_cell(index) * scale), scale))
+            .collect()
+    };
+    let get = |cell: IVec3| {
+        let cell = cell + 1;
+        sampled[(cell.x + cell.y * side + cell.z * side * side) as usize]
+    };
+
+    for (index, (block, _)) in sampled.iter().enumerate() {
+        let cell = to_cell(index as i32);
+        let Some(block) = block else {
+            continue;
+        };
+        if cell.cmplt(IVec3::ZERO).any() || cell.cmpge(IVec3::splat(cells)).any() {
+            continue;
+        }
+        let is_surface = block.block_type.is_fluid
+            && !get(cell + IVec3::Y).0.is_some_and(|above| above.block_type.is_fluid);
+        let surface_drop = if is_surface { FLUID_SURFACE_DROP } else { 0.0 };
+        let (min, max) = (cell * scale, cell * scale + scale - 1);
+
+        for (side, offset) in NEIGHBOUR_OFFSETS.iter().enumerate() {
+            let (neighbour, light) = get(cell + *offset);
+            if is_side_covered(block, neighbour.as_ref()) {
+                continue;
+            }
+            let vertices = SIDES_VERTICES[side].map(|v| [
+                v.x + (if v.x > 0.0 { max.x } else { min.x }) as f32 * VOXEL_HALF_SIDE * 2.0,
+                v.y + (if v.y > 0.0 { max.y } else { min.y }) as f32 * VOXEL_HALF_SIDE * 2.0
+                    - if v.y > 0.0 { surface_drop } else { 0.0 },
+                v.z + (if v.z > 0.0 { max.z } else { min.z }) as f32 * VOXEL_HALF_SIDE * 2.0,
+            ]);
+            let front = cell + *offset;
+            let ao = get_ao(side, |offset| get(front + offset).0.is_some_and(|block| block.block_type.is_opaque));
+            let (color, rect) = get_side_texture(block, side, atlas);
+            let buffers = if block.block_type.is_opaque { &mut opaque } else { &mut translucent };
+            buffers.add_quad(vertices, side, get_side_uvs(side, rect), get_vertex_colors(color, light, &ao), get_side_indices(&ao));
+        }
+    }
+
+    let quads_counter = opaque.len() + translucent.len();
+    opaque.apply_to(&mut meshes.opaque);
+    translucent.apply_to(&mut meshes.translucent);
+
+    let total = now.elapsed();
+    if quads_counter > 0 {
+        info!("Chunk meshed at level of detail {} ({quads_counter} quads). Total time: {total:.0?}.", lod.level);
+    }
+    meshes
+}
+
+/// Block filling the cell of `scale` blocks on each axis starting at `min`, and the brightest light in it.
+/// Cells are aligned to chunks, so all their blocks are read from a single one.
+fn sample_cell(world: &SeamedWorld, min: WorldBlockPos, scale: i32) -> (Option<Block>, Light) {
+    let chunk_pos = ChunkPos::from(min);
+    // Chunks across seams are hidden from the blocks but still give their light.
+    let Some(light_chunk) = world.world.get_chunk(&chunk_pos) else {
+        return (None, Light::SKY);
+    };
+    let chunk = world.get_chunk(&chunk_pos);
+    let min = BlockPos::from(min);
+
+    let mut light = Light::DARK;
+    let mut amount_of_blocks = 0;
+    let mut top_layer: Option<i32> = None;
+    let mut top_blocks: Vec<(Block, usize)> = Vec::new();
+    for y in (0..scale).rev() {
+        for z in 0..scale {
+            for x in 0..scale {
+                let pos = BlockPos(min.0 + IVec3::new(x, y, z));
+                let block_light = light_chunk.get_light_at(&pos).unwrap();
+                if block_light.level() > light.level() {
+                    light = block_light;
+                }
+                let Some(block) = chunk.and_then(|chunk| chunk.get_block_at(&pos).ok().copied().flatten()) else {
+                    continue;
+                };
+
+                amount_of_blocks += 1;
+                if *top_layer.get_or_insert(y) != y {
+                    continue;
+                }
+                match top_blocks.iter_mut().find(|(top_block, _)| *top_block == block) {
+                    Some((_, count)) => *count += 1,
+                    None => top_blocks.push((block, 1)),
+                }
+            }
+        }
+    }
+
+    let block = (amount_of_blocks * 2 >= scale * scale * scale)
+        .then(|| top_blocks.into_iter().max_by_key(|(_, count)| *count).map(|(block, _)| block))
+        .flatten();
+    (block, light)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use bevy::prelude::Mesh;
+    use bevy::render::mesh::VertexAttributeValues;
+    use crate::map::chunk::BlockType;
+    use crate::map::render::{ChunkMesher, CulledMesher};
+    use crate::utils::CHUNK_SIZE;
+    use super::*;
+
+    fn solid_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+        for i in 0..Chunk::SIZE {
+            chunk.set_block(&BlockPos::from_index(i), &BlockType::STONE).unwrap();
+        }
+        chunk
+    }
+
+    /// Quads of the mesh facing `normal` whose vertices all have `x`.
+    fn count_quads_at_x(mesh: &Mesh, normal: [f32; 3], x: f32) -> usize {
+        let (Some(VertexAttributeValues::Float32x3(positions)), Some(VertexAttributeValues::Float32x3(normals))) =
+            (mesh.attribute(Mesh::ATTRIBUTE_POSITION), mesh.attribute(Mesh::ATTRIBUTE_NORMAL)) else {
+            return 0;
+        };
+        positions.chunks(4)
+            .zip(normals.chunks(4))
+            .filter(|(quad, normals)| normals[0] == normal && quad.iter().all(|vertex| vertex[0] == x))
+            .count()
+    }
+
+    #[test]
+    fn seams_between_levels_of_detail_are_closed() {
+        let center = ChunkPos::new(0, 0, 0);
+        let (near_pos, far_pos) = (ChunkPos::new(2, 0, 0), ChunkPos::new(3, 0, 0));
+        let (near_lod, far_lod) = (ChunkLod::at(&near_pos, &center), ChunkLod::at(&far_pos, &center));
+        assert_eq!(near_lod.level, 0);
+        assert_ne!(near_lod.seams & 1 << 2, 0);
+        assert_eq!(far_lod.level, 1);
+        assert_ne!(far_lod.seams & 1 << 3, 0);
+
+        let (near, far) = (solid_chunk(), solid_chunk());
+        let chunks = HashMap::from([(near_pos, &near), (far_pos, &far)]);
+        let atlas = AtlasLayout::default();
+
+        let world = SeamedWorld { world: &chunks, chunk_pos: near_pos, seams: near_lod.seams };
+        let near_meshes = CulledMesher.create_mesh(&near, &near_pos, &world, &atlas);
+        let far_meshes = create_lod_mesh(&far_pos, &chunks, &atlas, far_lod);
+
+        let near_side = CHUNK_SIZE as f32 - VOXEL_HALF_SIDE;
+        assert_eq!(count_quads_at_x(&near_meshes.opaque, [1.0, 0.0, 0.0], near_side), CHUNK_SIZE * CHUNK_SIZE);
+        assert_eq!(count_quads_at_x(&far_meshes.opaque, [-1.0, 0.0, 0.0], -VOXEL_HALF_SIDE), CHUNK_SIZE * CHUNK_SIZE / 4);
+
+        // Without the seam the sides between the chunks are hidden.
+        let unseamed = create_lod_mesh(&far_pos, &chunks, &atlas, ChunkLod { seams: 0, ..far_lod });
+        assert_eq!(count_quads_at_x(&unseamed.opaque, [-1.0, 0.0, 0.0], -VOXEL_HALF_SIDE), 0);
+    }
+
+    #[test]
+    fn cells_in_corners_are_shaded() {
+        let mut chunk = Chunk::new();
+        chunk.fill_light(Light::SKY);
+        for i in 0..Chunk::SIZE {
+            let pos = BlockPos::from_index(i);
+            // A floor with a wall along one of its edges.
+            if pos.0.y < 2 || pos.0.x < 2 {
+                chunk.set_block(&pos, &BlockType::STONE).unwrap();
+            }
+        }
+        let pos = ChunkPos::new(0, 0, 0);
+        let chunks = HashMap::from([(pos, &chunk)]);
+        let meshes = create_lod_mesh(&pos, &chunks, &AtlasLayout::default(), ChunkLod { level: 1, seams: 0 });
+
+        let (Some(VertexAttributeValues::Float32x4(colors)), Some(VertexAttributeValues::Float32x3(normals))) =
+            (meshes.opaque.attribute(Mesh::ATTRIBUTE_COLOR), meshes.opaque.attribute(Mesh::ATTRIBUTE_NORMAL)) else {
+            panic!("LOD meshes have vertex colors and normals");
+        };
+        let brightness: Vec<_> = colors.iter()
+            .zip(normals)
+            .filter(|(_, normal)| **normal == [0.0, 1.0, 0.0])
+            .map(|(color, _)| color[0])
+            .collect();
+        let brightest = brightness.iter().copied().fold(0.0, f32::max);
+        assert!(brightness.iter().any(|&value| value < brightest), "floor vertices along the wall are darker");
+    }
+}
@@ -1,4 +1,10 @@
 mod static_voxel_render;
+mod chunk_mesher;
 mod culled_chunk_mesher;
+mod greedy_chunk_mesher;
+mod lod_chunk_mesher;
+mod block_atlas;
 
-pub use static_voxel_render::*;
\ No newline at end of file
+pub use static_voxel_render::*;
+pub use chunk_mesher::*;
+pub use block_atlas::*;
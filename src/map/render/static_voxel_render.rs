@@ -1,100 +1,283 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use bevy::ecs::component::Tick;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use crate::map::chunk::{Block, Chunk};
-use crate::map::render::culled_chunk_mesher::CulledChunkMesher;
-use crate::utils::{ChunkPos, WorldPos};
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use crate::map::chunk::Chunk;
+use crate::map::lighting::LightingSystems;
+use crate::map::generator::CUBE_SIDE;
+use crate::map::render::{build_block_atlas, ActiveChunkMesher, BlockAtlas, ChunkMesher, ChunkMeshes, CulledMesher};
+use crate::map::render::lod_chunk_mesher::{create_lod_mesh, ChunkLod, SeamedWorld};
+use crate::map::voxel_world::{ChunkIndex, VoxelAccess, VoxelWorld};
+use crate::utils::{surrounding_offsets, ChunkPos, WorldPos, CHUNK_SIZE_F32, NEIGHBOUR_OFFSETS};
 
-pub struct StaticVoxelRenderPlugin;
+pub struct StaticVoxelRenderPlugin {
+    pub mesher: Arc<dyn ChunkMesher>,
+}
+
+impl StaticVoxelRenderPlugin {
+    pub fn with_mesher(mesher: impl ChunkMesher + 'static) -> Self {
+        StaticVoxelRenderPlugin {
+            mesher: Arc::new(mesher),
+        }
+    }
+}
+
+impl Default for StaticVoxelRenderPlugin {
+    fn default() -> Self {
+        Self::with_mesher(CulledMesher)
+    }
+}
 
 impl Plugin for StaticVoxelRenderPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(PostUpdate, (spawn_mesh, remesh_neighbour_chunks_after_spawn));
+            .insert_resource(ActiveChunkMesher(self.mesher.clone()))
+            .init_resource::<MeshingTasks>()
+            .insert_resource(LodCenter(ChunkPos::new(0, 0, 0)))
+            .add_systems(Startup, build_block_atlas)
+            .add_systems(PostUpdate, (update_lod_center, spawn_mesh, remesh_neighbour_chunks_after_spawn, update_mesh, update_lods, apply_finished_meshes)
+                .chain()
+                .after(LightingSystems));
     }
 }
 
-const SIDES_OFFSETS: [(i32, i32, i32); 4] = [(1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)];
+/// Horizontal neighbours, diagonal ones included as their corners are shaded by the chunk.
+const SIDES_OFFSETS: [(i32, i32, i32); 8] = [
+    (1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1),
+    (1, 0, 1), (1, 0, -1), (-1, 0, 1), (-1, 0, -1),
+];
+
+/// Remeshes updated chunks and the neighbours whose bordering blocks were updated.
+fn update_mesh(updated_chunks: Query<(Entity, &ChunkPos, Ref<Chunk>), Changed<Chunk>>,
+               chunks: Query<Ref<Chunk>>,
+               index: Res<ChunkIndex>,
+               mut meshing: ChunkMeshing,) {
+    let mut chunks_to_update = HashMap::new();
+    for (entity, pos, chunk) in &updated_chunks {
+        if !chunk.is_updated {
+            continue;
+        }
+
+        chunks_to_update.insert(*pos, entity);
+        for offset in surrounding_offsets() {
+            // Diagonal neighbours are touched only by updates at the edges or corners towards them.
+            let is_touched = NEIGHBOUR_OFFSETS.iter()
+                .enumerate()
+                .filter(|(_, side)| side.dot(offset) > 0)
+                .all(|(side, _)| chunk.is_border_updated(side));
+            if !is_touched {
+                continue;
+            }
+            let neighbour_pos = ChunkPos(pos.0 + offset);
+            if let Some(neighbour) = index.get(&neighbour_pos) {
+                chunks_to_update.insert(neighbour_pos, neighbour);
+            }
+        }
+    }
+
+    for (pos, entity) in chunks_to_update {
+        if let Ok(chunk) = chunks.get(entity) {
+            meshing.queue(entity, &pos, chunk.last_changed());
+        }
+    }
+}
+
+/// Meshes being built on the [`AsyncComputeTaskPool`] together with the change tick
+/// of the chunk and the level of detail they were built from.
+#[derive(Resource, Default)]
+struct MeshingTasks(HashMap<Entity, (Tick, ChunkLod, Task<ChunkMeshes>)>);
+
+/// Chunk the levels of detail are centred on, the one with the camera.
+#[derive(Resource)]
+struct LodCenter(ChunkPos);
+
+/// Child entity drawing the translucent blocks of a chunk.
+#[derive(Component)]
+struct TranslucentMesh(Entity);
+
+/// Everything needed to start meshing chunks.
+#[derive(SystemParam)]
+struct ChunkMeshing<'w, 's> {
+    world: VoxelWorld<'w, 's>,
+    mesher: Res<'w, ActiveChunkMesher>,
+    atlas: Res<'w, BlockAtlas>,
+    center: Res<'w, LodCenter>,
+    tasks: ResMut<'w, MeshingTasks>,
+}
+
+impl ChunkMeshing<'_, '_> {
+    /// Starts building the meshes of a chunk from a snapshot of it and all chunks around it,
+    /// at the level of detail for its distance from the camera.
+    /// `tick` is the last change of the chunk. Replacing a previous task of the chunk cancels it.
+    fn queue(&mut self, entity: Entity, chunk_pos: &ChunkPos, tick: Tick) {
+        let snapshot = self.world.snapshot(std::iter::once(*chunk_pos).chain(surrounding_offsets()
+            .map(|offset| ChunkPos(chunk_pos.0 + offset))));
+        let mesher = self.mesher.0.clone();
+        let layout = self.atlas.layout.clone();
+        let lod = ChunkLod::at(chunk_pos, &self.center.0);
+        let chunk_pos = *chunk_pos;
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            if lod.level > 0 {
+                return create_lod_mesh(&chunk_pos, &snapshot, &layout, lod);
+            }
+            let world = SeamedWorld { world: &snapshot, chunk_pos, seams: lod.seams };
+            let chunk = snapshot.get_chunk(&chunk_pos).unwrap();
+            mesher.create_mesh(chunk, &chunk_pos, &world, &layout)
+        });
+        self.tasks.0.insert(entity, (tick, lod, task));
+    }
+
+    /// Whether the chunk has a mesh being built at the level of detail it needs now.
+    fn is_queued(&self, entity: Entity, chunk_pos: &ChunkPos) -> bool {
+        self.tasks.0.get(&entity).is_some_and(|(_, lod, _)| *lod == ChunkLod::at(chunk_pos, &self.center.0))
+    }
+}
 
-fn spawn_mesh(query: Query<(Entity, &ChunkPos, &Chunk), Added<Chunk>>,
-              mut commands: Commands,
-              mut materials: ResMut<Assets<StandardMaterial>>,
-              mut meshes: ResMut<Assets<Mesh>>,) {
-    let map = HashMap::from_iter(query.iter()
-        .map(|(_, chunk_pos, chunk)| (*chunk_pos, chunk)));
+fn update_lod_center(cameras: Query<&Transform, With<Camera>>,
+                     mut center: ResMut<LodCenter>,) {
+    let Some(camera) = cameras.iter().next() else {
+        return;
+    };
+    let camera_chunk = ChunkPos::new(
+        (camera.translation.x / (CHUNK_SIZE_F32 * CUBE_SIDE)).floor() as i32,
+        0,
+        (camera.translation.z / (CHUNK_SIZE_F32 * CUBE_SIDE)).floor() as i32);
+    if center.0 != camera_chunk {
+        center.0 = camera_chunk;
+    }
+}
+
+/// Remeshes the chunks whose level of detail has changed as the camera moved.
+fn update_lods(chunks: Query<(Entity, &ChunkPos, Ref<Chunk>, &ChunkLod)>,
+               mut meshing: ChunkMeshing,) {
+    if !meshing.center.is_changed() {
+        return;
+    }
+
+    let mut counter = 0;
+    for (entity, pos, chunk, lod) in &chunks {
+        if *lod == ChunkLod::at(pos, &meshing.center.0) || meshing.is_queued(entity, pos) {
+            continue;
+        }
+        meshing.queue(entity, pos, chunk.last_changed());
+        counter += 1;
+    }
+    if counter > 0 {
+        info!("Camera moved, remeshing {counter} chunks at new levels of detail");
+    }
+}
+
+fn spawn_mesh(query: Query<(Entity, &ChunkPos, Ref<Chunk>), Added<Chunk>>,
+              mut meshing: ChunkMeshing,) {
     let mut counter = 0;
     for (entity, chunk_pos, chunk) in &query {
-        commands
-            .entity(entity)
-            .insert(Mesh3d(meshes.add(chunk.create_mesh_culled(chunk_pos, &map))))
-            .insert(MeshMaterial3d(materials.add(StandardMaterial {
-                unlit: true,
-                ..default()
-            })))
-            .insert(Transform::from_translation(WorldPos::from(*chunk_pos).0))
-            .insert(Visibility::Visible);
+        meshing.queue(entity, chunk_pos, chunk.last_changed());
         counter += 1;
     }
     if counter > 0 {
-        info!("Spawned and meshed {counter} chunks");
+        info!("Spawned {counter} chunks, meshing them");
     }
 }
 
 fn remesh_neighbour_chunks_after_spawn(fresh_chunks: Query<&ChunkPos, Added<Chunk>>,
-                                       query: Query<(&ChunkPos, &Chunk, &Mesh3d)>,
-                                       mut meshes: ResMut<Assets<Mesh>>) {
+                                       chunks: Query<Ref<Chunk>>,
+                                       index: Res<ChunkIndex>,
+                                       mut meshing: ChunkMeshing,) {
     if (fresh_chunks.is_empty()) {
         return;
     }
 
     let mut chunks_to_update = HashSet::new();
-    for pos in fresh_chunks {
+    for pos in &fresh_chunks {
         for (x, y, z) in SIDES_OFFSETS {
             let neighbour_pos = ChunkPos::new(pos.0.x + x, pos.0.y + y, pos.0.z + z);
             chunks_to_update.insert(neighbour_pos);
         }
     }
+    for pos in &fresh_chunks {
+        chunks_to_update.remove(pos);
+    }
 
     let fresh_chunks_len = fresh_chunks.iter().count();
     let chunks_to_update_len = chunks_to_update.len();
     info!("Spawned {fresh_chunks_len} chunks, remeshing up to {chunks_to_update_len} chunks");
 
-    let map = HashMap::from_iter(query.iter()
-        .map(|(chunk_coordinates, chunk, _)| (*chunk_coordinates, chunk)));
+    for pos in chunks_to_update {
+        let Some(entity) = index.get(&pos) else {
+            continue;
+        };
+        if let Ok(chunk) = chunks.get(entity) {
+            meshing.queue(entity, &pos, chunk.last_changed());
+        }
+    }
+}
+
+fn apply_finished_meshes(chunks: Query<(Ref<Chunk>, &ChunkPos, Option<&Mesh3d>, Option<&TranslucentMesh>)>,
+                         translucent_meshes: Query<&Mesh3d, Without<Chunk>>,
+                         mut meshing: ChunkMeshing,
+                         mut commands: Commands,
+                         mut materials: ResMut<Assets<StandardMaterial>>,
+                         mut meshes: ResMut<Assets<Mesh>>,) {
+    // Tasks of despawned chunks are dropped and so cancelled.
+    meshing.tasks.0.retain(|&entity, _| chunks.contains(entity));
+
+    let finished: Vec<_> = meshing.tasks.0.iter()
+        .filter(|(_, (_, _, task))| task.is_finished())
+        .map(|(&entity, _)| entity)
+        .collect();
 
     let mut counter = 0;
+    for entity in finished {
+        let (tick, lod, task) = meshing.tasks.0.remove(&entity).unwrap();
+        let chunk_meshes = block_on(task);
+        let (chunk, chunk_pos, mesh_handle, translucent) = chunks.get(entity).unwrap();
+        if chunk.last_changed() != tick || lod != ChunkLod::at(chunk_pos, &meshing.center.0) {
+            // The chunk has changed or the camera has moved while it was meshed, the mesh is stale.
+            meshing.queue(entity, chunk_pos, chunk.last_changed());
+            continue;
+        }
 
-    for (pos, chunk, handle) in query.iter() {
-        if (chunks_to_update.contains(pos)) {
-            let mesh = meshes.get_mut(handle.id()).unwrap();
-            chunk.update_mesh_culled(mesh, pos, &map);
-            counter += 1;
+        let translucent_handle = translucent.and_then(|translucent| translucent_meshes.get(translucent.0).ok());
+        match (mesh_handle, translucent_handle) {
+            (Some(mesh_handle), Some(translucent_handle)) => {
+                meshes.insert(mesh_handle.id(), chunk_meshes.opaque);
+                meshes.insert(translucent_handle.id(), chunk_meshes.translucent);
+                commands.entity(entity).insert(lod);
+            }
+            _ => {
+                let translucent = commands
+                    .spawn((
+                        Mesh3d(meshes.add(chunk_meshes.translucent)),
+                        MeshMaterial3d(materials.add(StandardMaterial {
+                            base_color_texture: Some(meshing.atlas.image.clone()),
+                            alpha_mode: AlphaMode::Blend,
+                            unlit: true,
+                            ..default()
+                        })),
+                        Transform::default(),
+                        Visibility::Inherited,
+                    ))
+                    .id();
+                commands
+                    .entity(entity)
+                    .insert(Mesh3d(meshes.add(chunk_meshes.opaque)))
+                    .insert(MeshMaterial3d(materials.add(StandardMaterial {
+                        base_color_texture: Some(meshing.atlas.image.clone()),
+                        unlit: true,
+                        ..default()
+                    })))
+                    .insert(Transform::from_translation(WorldPos::from(*chunk_pos).0))
+                    .insert(Visibility::Visible)
+                    .insert(TranslucentMesh(translucent))
+                    .insert(lod)
+                    .add_child(translucent);
+            }
         }
+        counter += 1;
+    }
+    if counter > 0 {
+        info!("Meshed {counter} chunks");
     }
-    info!("Remeshed {counter} chunks");
 }
-
-//
-// fn update_mesh(mut query: Query<(&ChunkCoordinates, &mut Chunk, &Mesh3d)>, mut meshes: ResMut<Assets<Mesh>>) {
-//     let mut updated_chunks = vec![];
-//     for (pos, mut chunk, _) in query.iter_mut() {
-//         if chunk.is_updated {
-//             chunk.is_updated = false;
-//             updated_chunks.push(*pos);
-//         }
-//     }
-//
-//     if (updated_chunks.is_empty()){
-//         return;
-//     }
-//
-//     let map = query.iter()
-//         .map(|(chunk_coordinates, chunk, _)| (chunk_coordinates, chunk))
-//         .collect::<Vec<_>>();
-//
-//     for (pos, chunk, handle) in query.iter() {
-//         if (updated_chunks.contains(pos)) {
-//             let mesh = meshes.get_mut(&handle.0).unwrap();
-//             chunk.update_mesh_culled_interchunk(mesh, pos, &map);
-//         }
-//     }
-// }
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use crate::map::chunk::{Block, BlockType, Chunk, Light};
+use crate::utils::{BlockPos, ChunkPos, WorldBlockPos};
+
+/// Keeps [`ChunkIndex`] in sync with spawned and despawned chunks and announces [`BlockChanged`].
+pub struct VoxelWorldPlugin;
+
+impl Plugin for VoxelWorldPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ChunkIndex>()
+            .add_event::<BlockChanged>()
+            .add_observer(index_added_chunk)
+            .add_observer(unindex_removed_chunk);
+    }
+}
+
+/// Entities of all loaded chunks by their position.
+#[derive(Resource, Default)]
+pub struct ChunkIndex(HashMap<ChunkPos, Entity>);
+
+impl ChunkIndex {
+    pub fn get(&self, pos: &ChunkPos) -> Option<Entity> {
+        self.0.get(pos).copied()
+    }
+
+    pub fn contains(&self, pos: &ChunkPos) -> bool {
+        self.0.contains_key(pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct ChunkNotLoadedError();
+
+/// Sent by [`VoxelWorldMut`] for every block that has actually changed.
+#[derive(Event, Copy, Clone)]
+pub struct BlockChanged {
+    pub pos: WorldBlockPos,
+    pub previous: Option<Block>,
+    pub current: Option<Block>,
+}
+
+/// Read access to blocks of the world regardless of the chunk they are in.
+pub trait VoxelAccess {
+    fn get_chunk(&self, pos: &ChunkPos) -> Option<&Chunk>;
+
+    /// Block at `pos`, `None` if it is empty or its chunk is not loaded.
+    fn get_block(&self, pos: &WorldBlockPos) -> Option<Block> {
+        let chunk = self.get_chunk(&(*pos).into())?;
+        chunk.get_block_at(&BlockPos::from(*pos)).ok().copied().flatten()
+    }
+
+    fn has_block(&self, pos: &WorldBlockPos) -> bool {
+        self.get_block(pos).is_some()
+    }
+
+    /// Light at `pos`, full sky light if its chunk is not loaded.
+    fn get_light(&self, pos: &WorldBlockPos) -> Light {
+        match self.get_chunk(&(*pos).into()) {
+            Some(chunk) => chunk.get_light_at(&BlockPos::from(*pos)).unwrap(),
+            None => Light::SKY,
+        }
+    }
+
+    fn is_loaded(&self, pos: &WorldBlockPos) -> bool {
+        self.get_chunk(&(*pos).into()).is_some()
+    }
+
+    /// Blocks around `pos` in [`NEIGHBOUR_OFFSETS`](crate::utils::NEIGHBOUR_OFFSETS) order.
+    fn get_neighbours(&self, pos: &WorldBlockPos) -> [Option<Block>; 6] {
+        pos.neighbours().map(|neighbour| self.get_block(&neighbour))
+    }
+}
+
+impl VoxelAccess for HashMap<ChunkPos, &Chunk> {
+    fn get_chunk(&self, pos: &ChunkPos) -> Option<&Chunk> {
+        self.get(pos).copied()
+    }
+}
+
+/// Owned copy of some chunks that can be read outside of systems.
+#[derive(Default)]
+pub struct VoxelSnapshot(HashMap<ChunkPos, Chunk>);
+
+impl VoxelAccess for VoxelSnapshot {
+    fn get_chunk(&self, pos: &ChunkPos) -> Option<&Chunk> {
+        self.0.get(pos)
+    }
+}
+
+/// Read-only access to the loaded world.
+#[derive(SystemParam)]
+pub struct VoxelWorld<'w, 's> {
+    index: Res<'w, ChunkIndex>,
+    chunks: Query<'w, 's, &'static Chunk>,
+}
+
+impl VoxelAccess for VoxelWorld<'_, '_> {
+    fn get_chunk(&self, pos: &ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(self.index.get(pos)?).ok()
+    }
+}
+
+impl VoxelWorld<'_, '_> {
+    /// Copies the loaded chunks among `positions`. Chunk data is shared until either copy is changed.
+    pub fn snapshot(&self, positions: impl IntoIterator<Item = ChunkPos>) -> VoxelSnapshot {
+        VoxelSnapshot(positions.into_iter()
+            .filter_map(|pos| Some((pos, self.get_chunk(&pos)?.clone())))
+            .collect())
+    }
+}
+
+/// Read and write access to the loaded world.
+#[derive(SystemParam)]
+pub struct VoxelWorldMut<'w, 's> {
+    index: Res<'w, ChunkIndex>,
+    chunks: Query<'w, 's, &'static mut Chunk>,
+    events: EventWriter<'w, BlockChanged>,
+}
+
+impl VoxelAccess for VoxelWorldMut<'_, '_> {
+    fn get_chunk(&self, pos: &ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(self.index.get(pos)?).ok()
+    }
+}
+
+impl VoxelWorldMut<'_, '_> {
+    pub fn get_chunk_mut(&mut self, pos: &ChunkPos) -> Option<Mut<'_, Chunk>> {
+        self.chunks.get_mut(self.index.get(pos)?).ok()
+    }
+
+    /// Places a block at `pos` and returns the previous one.
+    pub fn set_block(&mut self, pos: &WorldBlockPos, value: &'static BlockType) -> Result<Option<Block>, ChunkNotLoadedError> {
+        self.replace_block(pos, Some(value.into()))
+    }
+
+    /// Removes the block at `pos` and returns it.
+    pub fn remove_block(&mut self, pos: &WorldBlockPos) -> Result<Option<Block>, ChunkNotLoadedError> {
+        self.replace_block(pos, None)
+    }
+
+    pub fn replace_block(&mut self, pos: &WorldBlockPos, value: Option<Block>) -> Result<Option<Block>, ChunkNotLoadedError> {
+        let mut chunk = self.get_chunk_mut(&(*pos).into()).ok_or(ChunkNotLoadedError())?;
+        // Block position converted from a world position is always inside the chunk.
+        let previous = chunk.replace_block(&BlockPos::from(*pos), value).unwrap();
+        if previous != value {
+            self.events.write(BlockChanged { pos: *pos, previous, current: value });
+        }
+        Ok(previous)
+    }
+}
+
+fn index_added_chunk(trigger: Trigger<OnAdd, Chunk>,
+                     positions: Query<&ChunkPos>,
+                     mut index: ResMut<ChunkIndex>) {
+    let entity = trigger.target();
+    if let Ok(pos) = positions.get(entity) {
+        index.0.insert(*pos, entity);
+    }
+}
+
+fn unindex_removed_chunk(trigger: Trigger<OnRemove, Chunk>,
+                         positions: Query<&ChunkPos>,
+                         mut index: ResMut<ChunkIndex>) {
+    let entity = trigger.target();
+    if let Ok(pos) = positions.get(entity) {
+        if index.get(pos) == Some(entity) {
+            index.0.remove(pos);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use bevy::input::mouse::MouseMotion;
+use bevy::prelude::*;
+use bevy_fly_camera::FlyCamera;
+use crate::map::voxel_world::{VoxelAccess, VoxelWorld};
+use crate::utils::{WorldBlockPos, WORLD_Y_OFFSET};
+use super::player_bounds;
+
+const GRAVITY: f32 = 28.0;
+const JUMP_SPEED: f32 = 8.5;
+const MAX_FALL_SPEED: f32 = 50.0;
+const WALK_SPEED: f32 = 4.3;
+const SPRINT_SPEED: f32 = 5.6;
+const SNEAK_SPEED: f32 = 1.3;
+/// Highest ledge the player walks onto without jumping.
+const STEP_HEIGHT: f32 = 1.0;
+/// Gap kept between the player and blocks, so touching a block is not overlapping it.
+const SKIN: f32 = 1e-3;
+/// Longest simulated frame, the player slows down on longer ones instead of tunneling.
+const MAX_FRAME_TIME: f32 = 0.05;
+
+const TOGGLE_FLYING_KEY: KeyCode = KeyCode::KeyF;
+const JUMP_KEY: KeyCode = KeyCode::Space;
+const SPRINT_KEY: KeyCode = KeyCode::ControlLeft;
+const SNEAK_KEY: KeyCode = KeyCode::ShiftLeft;
+
+/// Walking state of a player. Flying players are moved by [`FlyCamera`] instead.
+#[derive(Component, Default)]
+pub struct PlayerBody {
+    pub velocity: Vec3,
+    pub is_grounded: bool,
+    pub is_flying: bool,
+}
+
+pub(super) fn toggle_flying(keys: Res<ButtonInput<KeyCode>>,
+                            mut players: Query<&mut PlayerBody>) {
+    if !keys.just_pressed(TOGGLE_FLYING_KEY) {
+        return;
+    }
+    for mut body in &mut players {
+        body.is_flying = !body.is_flying;
+        body.velocity = Vec3::ZERO;
+        info!("Flying: {}", body.is_flying);
+    }
+}
+
+/// Enables [`FlyCamera`] only for flying players.
+pub(super) fn sync_fly_camera(mut players: Query<(&PlayerBody, &mut FlyCamera)>) {
+    for (body, mut fly) in &mut players {
+        if fly.enabled != body.is_flying {
+            fly.enabled = body.is_flying;
+        }
+    }
+}
+
+/// Mouse look of walking players. Shares the angles with [`FlyCamera`], so toggling flying keeps the view.
+pub(super) fn look_around(mut motion: EventReader<MouseMotion>,
+                          time: Res<Time>,
+                          mut players: Query<(&mut Transform, &PlayerBody, &mut FlyCamera)>) {
+    let delta: Vec2 = motion.read().map(|event| event.delta).sum();
+    for (mut transform, body, mut fly) in &mut players {
+        if body.is_flying {
+            continue;
+        }
+        fly.yaw -= delta.x * fly.sensitivity * time.delta_secs();
+        fly.pitch = (fly.pitch + delta.y * fly.sensitivity * time.delta_secs()).clamp(-89.0, 89.9);
+        transform.rotation = Quat::from_axis_angle(Vec3::Y, fly.yaw.to_radians())
+            * Quat::from_axis_angle(-Vec3::X, fly.pitch.to_radians());
+    }
+}
+
+pub(super) fn walk(keys: Res<ButtonInput<KeyCode>>,
+                   time: Res<Time>,
+                   world: VoxelWorld,
+                   mut players: Query<(&mut Transform, &mut PlayerBody, &FlyCamera)>) {
+    let dt = time.delta_secs().min(MAX_FRAME_TIME);
+    for (mut transform, mut body, fly) in &mut players {
+        if body.is_flying {
+            continue;
+        }
+        let block_offset = Vec3::new(0.5, WORLD_Y_OFFSET + 0.5, 0.5);
+        let (min, max) = player_bounds(transform.translation);
+        let mut bounds = (min + block_offset, max + block_offset);
+        // Wait for the ground to load.
+        let column = WorldBlockPos::new(bounds.0.x.floor() as i32, 0, bounds.0.z.floor() as i32);
+        if !world.is_loaded(&column) {
+            continue;
+        }
+
+        let mut input = Vec3::ZERO;
+        if keys.pressed(KeyCode::KeyW) { input.z -= 1.0; }
+        if keys.pressed(KeyCode::KeyS) { input.z += 1.0; }
+        if keys.pressed(KeyCode::KeyA) { input.x -= 1.0; }
+        if keys.pressed(KeyCode::KeyD) { input.x += 1.0; }
+        let speed = if keys.pressed(SNEAK_KEY) {
+            SNEAK_SPEED
+        } else if keys.pressed(SPRINT_KEY) {
+            SPRINT_SPEED
+        } else {
+            WALK_SPEED
+        };
+        let horizontal = Quat::from_rotation_y(fly.yaw.to_radians()) * input.normalize_or_zero() * speed;
+        body.velocity.x = horizontal.x;
+        body.velocity.z = horizontal.z;
+        if body.is_grounded && keys.pressed(JUMP_KEY) {
+            body.velocity.y = JUMP_SPEED;
+        }
+        move_body(&world, &mut bounds, &mut body, dt);
+
+        transform.translation += bounds.0 - block_offset - min;
+    }
+}
+
+/// Pulls the body down and moves its `bounds` given in block space by its velocity for `dt` seconds.
+/// Floors ground it, ceilings stop its rise and ledges of at most [`STEP_HEIGHT`] are climbed while grounded.
+fn move_body(world: &dyn VoxelAccess, bounds: &mut (Vec3, Vec3), body: &mut PlayerBody, dt: f32) {
+    body.velocity.y = (body.velocity.y - GRAVITY * dt).max(-MAX_FALL_SPEED);
+
+    let delta = body.velocity * dt;
+    let moved = move_along(world, bounds, 1, delta.y);
+    body.is_grounded = delta.y < 0.0 && moved != delta.y;
+    if moved != delta.y {
+        body.velocity.y = 0.0;
+    }
+    for axis in [0, 2] {
+        let moved = move_along(world, bounds, axis, delta[axis]);
+        if moved != delta[axis] && body.is_grounded {
+            step_up(world, bounds, axis, delta[axis] - moved);
+        }
+    }
+}
+
+/// Blocks the player can't pass through. Unloaded columns are solid so the player doesn't fall out of the world,
+/// while loaded ones are open above their top chunk and solid below their bottom one.
+fn is_solid(world: &dyn VoxelAccess, pos: &WorldBlockPos) -> bool {
+    if !world.is_loaded(pos) {
+        return pos.0.y < 0 || !world.is_loaded(&WorldBlockPos(IVec3::new(pos.0.x, 0, pos.0.z)));
+    }
+    world.get_block(pos).is_some_and(|block| block.block_type.is_solid)
+}
+
+/// Moves `bounds` given in block space along `axis` until the first solid block and returns the travelled distance.
+fn move_along(world: &dyn VoxelAccess, bounds: &mut (Vec3, Vec3), axis: usize, delta: f32) -> f32 {
+    if delta == 0.0 {
+        return 0.0;
+    }
+    let (min, max) = *bounds;
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let cells = |a: usize| (min[a] + SKIN).floor() as i32..=(max[a] - SKIN).floor() as i32;
+    let is_layer_blocked = |layer: i32| cells(u).any(|i| cells(v).any(|j| {
+        let mut pos = IVec3::ZERO;
+        pos[axis] = layer;
+        pos[u] = i;
+        pos[v] = j;
+        is_solid(world, &WorldBlockPos(pos))
+    }));
+
+    // Only layers the bounds don't overlap yet can stop them.
+    let moved = if delta > 0.0 {
+        let first = (max[axis] - SKIN).floor() as i32 + 1;
+        let last = (max[axis] + delta).ceil() as i32 - 1;
+        (first..=last).find(|&layer| is_layer_blocked(layer))
+            .map_or(delta, |layer| (layer as f32 - SKIN - max[axis]).clamp(0.0, delta))
+    } else {
+        let first = (min[axis] + SKIN).floor() as i32 - 1;
+        let last = (min[axis] + delta).floor() as i32;
+        (last..=first).rev().find(|&layer| is_layer_blocked(layer))
+            .map_or(delta, |layer| (layer as f32 + 1.0 + SKIN - min[axis]).clamp(delta, 0.0))
+    };
+    bounds.0[axis] += moved;
+    bounds.1[axis] += moved;
+    moved
+}
+
+/// Climbs a ledge of at most [`STEP_HEIGHT`] blocking the horizontal movement along `axis`.
+fn step_up(world: &dyn VoxelAccess, bounds: &mut (Vec3, Vec3), axis: usize, remaining: f32) {
+    let mut raised = *bounds;
+    let lift = move_along(world, &mut raised, 1, STEP_HEIGHT);
+    if move_along(world, &mut raised, axis, remaining).abs() <= SKIN {
+        return;
+    }
+    move_along(world, &mut raised, 1, -lift);
+    *bounds = raised;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::map::chunk::{BlockType, Chunk};
+    use crate::player::PLAYER_EYE_HEIGHT;
+    use crate::utils::{BlockPos, ChunkPos};
+    use super::*;
+
+    const FLOOR: i32 = 10;
+
+    /// Chunk with a stone floor at [`FLOOR`] and stone at every one of `blocks`.
+    fn create_chunk(blocks: &[IVec3]) -> Chunk {
+        let mut chunk = Chunk::new();
+        for z in 0..32 {
+            for x in 0..32 {
+                chunk.set_block(&BlockPos::new(x, FLOOR, z), &BlockType::STONE).unwrap();
+            }
+        }
+        for block in blocks {
+            chunk.set_block(&BlockPos(*block), &BlockType::STONE).unwrap();
+        }
+        chunk
+    }
+
+    /// Block space bounds of a player standing with its feet centred at `feet`.
+    fn bounds_at(feet: Vec3) -> (Vec3, Vec3) {
+        player_bounds(feet + Vec3::Y * PLAYER_EYE_HEIGHT)
+    }
+
+    /// Moves the body for `frames` frames of [`MAX_FRAME_TIME`], keeping its horizontal velocity.
+    fn simulate(chunk: &Chunk, bounds: &mut (Vec3, Vec3), body: &mut PlayerBody, frames: usize) {
+        let world = HashMap::from([(ChunkPos::new(0, 0, 0), chunk)]);
+        for _ in 0..frames {
+            move_body(&world, bounds, body, MAX_FRAME_TIME);
+        }
+    }
+
+    #[test]
+    fn falling_body_lands_on_the_floor() {
+        let chunk = create_chunk(&[]);
+        let mut bounds = bounds_at(Vec3::new(10.5, FLOOR as f32 + 3.0, 10.5));
+        let mut body = PlayerBody::default();
+        simulate(&chunk, &mut bounds, &mut body, 20);
+
+        assert!(body.is_grounded);
+        assert_eq!(body.velocity.y, 0.0);
+        assert!((bounds.0.y - (FLOOR + 1) as f32).abs() <= 2.0 * SKIN, "feet at {}", bounds.0.y);
+    }
+
+    #[test]
+    fn ceiling_stops_a_jump() {
+        let ceiling: Vec<_> = (8..13).flat_map(|z| (8..13).map(move |x| IVec3::new(x, FLOOR + 3, z))).collect();
+        let chunk = create_chunk(&ceiling);
+        let mut bounds = bounds_at(Vec3::new(10.5, (FLOOR + 1) as f32 + SKIN, 10.5));
+        let mut body = PlayerBody { velocity: Vec3::Y * JUMP_SPEED, ..default() };
+        simulate(&chunk, &mut bounds, &mut body, 1);
+
+        assert_eq!(body.velocity.y, 0.0);
+        assert!(!body.is_grounded);
+        assert!((bounds.1.y - (FLOOR + 3) as f32).abs() <= 2.0 * SKIN, "head at {}", bounds.1.y);
+    }
+
+    #[test]
+    fn one_block_ledge_is_stepped_onto() {
+        let ledge: Vec<_> = (0..32).flat_map(|z| (12..32).map(move |x| IVec3::new(x, FLOOR + 1, z))).collect();
+        let chunk = create_chunk(&ledge);
+        let mut bounds = bounds_at(Vec3::new(11.0, (FLOOR + 1) as f32 + SKIN, 10.5));
+        let mut body = PlayerBody { velocity: Vec3::X * WALK_SPEED, ..default() };
+        simulate(&chunk, &mut bounds, &mut body, 20);
+
+        assert!(bounds.0.x > 12.0, "stopped at {}", bounds.0.x);
+        assert!((bounds.0.y - (FLOOR + 2) as f32).abs() <= 2.0 * SKIN, "feet at {}", bounds.0.y);
+        assert!(body.is_grounded);
+    }
+
+    #[test]
+    fn two_block_wall_blocks_the_way() {
+        let wall: Vec<_> = (0..32).flat_map(|z| [IVec3::new(12, FLOOR + 1, z), IVec3::new(12, FLOOR + 2, z)]).collect();
+        let chunk = create_chunk(&wall);
+        let mut bounds = bounds_at(Vec3::new(11.0, (FLOOR + 1) as f32 + SKIN, 10.5));
+        let mut body = PlayerBody { velocity: Vec3::X * WALK_SPEED, ..default() };
+        simulate(&chunk, &mut bounds, &mut body, 20);
+
+        assert!(bounds.1.x <= 12.0 && bounds.1.x > 12.0 - 2.0 * SKIN, "stopped at {}", bounds.1.x);
+        assert!((bounds.0.y - (FLOOR + 1) as f32).abs() <= 2.0 * SKIN, "feet at {}", bounds.0.y);
+    }
+}
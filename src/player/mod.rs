@@ -1,48 +1,115 @@
 use bevy::prelude::*;
-use itertools::Itertools;
-use crate::map::chunk::{Block, BlockType, Chunk};
-use crate::utils::{ChunkPos, WorldBlockPos, WorldPos};
+use controller::*;
+use crate::map::chunk::{BlockRegistry, BlockType};
+use crate::map::raycast::{raycast, RaycastHit};
+use crate::map::voxel_world::VoxelWorldMut;
+use crate::utils::{WorldBlockPos, WorldPos};
+
+mod controller;
+
+pub use controller::PlayerBody;
+
+/// Maximal distance from the eyes to a block the player can break or place.
+const REACH_DISTANCE: f32 = 6.0;
+const PLAYER_HALF_WIDTH: f32 = 0.3;
+const PLAYER_HEIGHT: f32 = 1.8;
+const PLAYER_EYE_HEIGHT: f32 = 1.6;
+const SELECTION_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3,
+    KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6,
+    KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+];
 
 #[derive(Component, Eq, PartialEq)]
 pub struct Player(usize);
 
+/// Block type placed by the player.
+#[derive(Component)]
+pub struct SelectedBlock(pub &'static BlockType);
+
+/// Corners of the player volume for the player's eyes at `eyes`.
+pub fn player_bounds(eyes: Vec3) -> (Vec3, Vec3) {
+    let feet = eyes - Vec3::Y * PLAYER_EYE_HEIGHT;
+    (feet - Vec3::new(PLAYER_HALF_WIDTH, 0.0, PLAYER_HALF_WIDTH),
+     feet + Vec3::new(PLAYER_HALF_WIDTH, PLAYER_HEIGHT, PLAYER_HALF_WIDTH))
+}
+
+fn intersects_block(bounds: (Vec3, Vec3), pos: &WorldBlockPos) -> bool {
+    let center = WorldPos::from(*pos).0;
+    let (min, max) = bounds;
+    (min - center).cmplt(Vec3::splat(0.5)).all() && (max - center).cmpgt(Vec3::splat(-0.5)).all()
+}
+
 fn setup_players(query: Query<Entity, With<Camera>>, mut commands: Commands) {
     for (i, e) in query.iter().enumerate() {
         println!("Player was added");
         commands
             .entity(e)
-            .insert(Player(i));
-    }
-}
-
-fn interact_with_chunk(players: Query<&Transform, With<Player>>,
-                       mut chunks: Query<(&ChunkPos, &mut Chunk)>) {
-    // let transform_op = players.iter().next();
-    // if transform_op.is_none() {
-    //     return;
-    // }
-    // let transform = transform_op.unwrap();
-    //
-    // let coords = std::iter::repeat(transform.translation)
-    //     .enumerate()
-    //     .map(|(i, pos)| pos + transform.forward() * i as f32)
-    //     .map (|pos| WorldPos(pos))
-    //     .map(|pos| WorldBlockPos(IVec3::new(pos.0.x as i32, pos.0.y as i32, pos.0.z as i32)))
-    //     .unique()
-    //     .map(|pos| ChunkPos::from(pos.into()))
-    //     .take(5);
-    //
-    // for (cc, (x, y, z)) in coords {
-    //     let chunk_op = chunks.iter_mut()
-    //         .find(|(&ccc, _)| ccc == cc)
-    //         .map(|(_, ch)| ch);
-    //     if let Some(mut chunk) = chunk_op {
-    //         unsafe {
-    //             chunk.set_unchecked(x as u8, y as u8, z as u8, Some(Block{block_type: &BlockType::COPPER}));
-    //             chunk.is_updated = true;
-    //         }
-    //     }
-    // }
+            .insert(Player(i))
+            .insert(SelectedBlock(&BlockType::STONE))
+            .insert(PlayerBody::default());
+    }
+}
+
+/// Number keys select one of the first placeable blocks of the registry.
+fn select_block(keys: Res<ButtonInput<KeyCode>>,
+                registry: Res<BlockRegistry>,
+                mut players: Query<&mut SelectedBlock, With<Player>>) {
+    let Some(n) = SELECTION_KEYS.iter().position(|&key| keys.just_pressed(key)) else {
+        return;
+    };
+    let Some(block_type) = registry.iter()
+        .filter(|block_type| !block_type.is_fluid && **block_type != BlockType::UNBREAKABLE)
+        .nth(n) else {
+        return;
+    };
+
+    info!("Selected block {}", block_type.name);
+    for mut selected in &mut players {
+        selected.0 = block_type;
+    }
+}
+
+/// Breaks the looked-at block on left click and places the selected block in front of it on right click.
+fn interact_with_chunk(players: Query<(&Transform, &SelectedBlock), With<Player>>,
+                       mouse: Res<ButtonInput<MouseButton>>,
+                       mut world: VoxelWorldMut) {
+    let is_breaking = mouse.just_pressed(MouseButton::Left);
+    let is_placing = mouse.just_pressed(MouseButton::Right);
+    if !is_breaking && !is_placing {
+        return;
+    }
+
+    for (transform, selected) in &players {
+        let ray = Ray3d::new(transform.translation, transform.forward());
+        let Some(hit) = raycast(&world, ray, REACH_DISTANCE) else {
+            continue;
+        };
+
+        if is_breaking {
+            break_block(&hit, &mut world);
+        } else {
+            place_block(&hit, selected.0, transform.translation, &mut world);
+        }
+    }
+}
+
+fn break_block(hit: &RaycastHit, world: &mut VoxelWorldMut) {
+    if *hit.block.block_type == BlockType::UNBREAKABLE {
+        return;
+    }
+    if world.remove_block(&hit.pos).is_err() {
+        warn!("Can't break block at {:?}, its chunk is not loaded", hit.pos);
+    }
+}
+
+fn place_block(hit: &RaycastHit, block_type: &'static BlockType, eyes: Vec3, world: &mut VoxelWorldMut) {
+    if intersects_block(player_bounds(eyes), &hit.adjacent) {
+        return;
+    }
+    if world.set_block(&hit.adjacent, block_type).is_err() {
+        warn!("Can't place block at {:?}, its chunk is not loaded", hit.adjacent);
+    }
 }
 
 pub struct YamcPlayerPlugin;
@@ -51,6 +118,7 @@ impl Plugin for YamcPlayerPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_systems(PostStartup, setup_players)
-            .add_systems(Update, interact_with_chunk);
+            .add_systems(Update, (toggle_flying, sync_fly_camera, look_around, walk).chain())
+            .add_systems(Update, (select_block, interact_with_chunk).chain());
     }
-}
\ No newline at end of file
+}
@@ -6,6 +6,26 @@ pub const CHUNK_SIZE_I32: i32 = CHUNK_SIZE as i32;
 pub const CHUNK_SIZE_F32: f32 = CHUNK_SIZE as f32;
 pub const CHUNK_SIZE_F64: f64 = CHUNK_SIZE as f64;
 
+/// Blocks with y = 0 are centered at y = `-WORLD_Y_OFFSET` in bevy world space.
+pub const WORLD_Y_OFFSET: f32 = 100.0;
+
+/// Offsets to the six face neighbours: top, bottom, right, left, forward, backward.
+pub const NEIGHBOUR_OFFSETS: [IVec3; 6] = [
+    IVec3::new(0, 1, 0),
+    IVec3::new(0, -1, 0),
+    IVec3::new(1, 0, 0),
+    IVec3::new(-1, 0, 0),
+    IVec3::new(0, 0, 1),
+    IVec3::new(0, 0, -1),
+];
+
+/// Offsets to all 26 cells around a cell, including the diagonal ones.
+pub fn surrounding_offsets() -> impl Iterator<Item = IVec3> {
+    (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+        .filter(|offset| *offset != IVec3::ZERO)
+}
+
 /// Block coordinates in world space.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct WorldBlockPos(pub IVec3);
@@ -23,6 +43,14 @@ pub struct BlockPos(pub IVec3);
 pub struct WorldPos(pub Vec3);
 
 impl WorldBlockPos {
+    pub fn new(x: i32, y: i32, z: i32) -> WorldBlockPos {
+        WorldBlockPos(IVec3::new(x, y, z))
+    }
+
+    pub fn neighbours(&self) -> [WorldBlockPos; 6] {
+        NEIGHBOUR_OFFSETS.map(|offset| WorldBlockPos(self.0 + offset))
+    }
+
     pub fn from (chunk: &ChunkPos, block: &BlockPos) -> WorldBlockPos {
         WorldBlockPos(IVec3::new(
             chunk.0.x * CHUNK_SIZE_I32 + block.0.x,
@@ -45,7 +73,7 @@ impl ChunkPos {
 impl From<ChunkPos> for WorldPos {
     fn from(value: ChunkPos) -> Self {
         WorldPos(Vec3::new((value.0.x as f32) * CHUNK_SIZE_F32,
-                           (value.0.y as f32) * CHUNK_SIZE_F32 - 100.0,
+                           (value.0.y as f32) * CHUNK_SIZE_F32 - WORLD_Y_OFFSET,
                            (value.0.z as f32) * CHUNK_SIZE_F32))
 
     }
@@ -76,13 +104,14 @@ impl BlockPos {
 
 impl From<WorldBlockPos> for WorldPos {
     fn from(value: WorldBlockPos) -> WorldPos {
-        WorldPos(Vec3::new(value.0.x as f32, value.0.y as f32 - 100., value.0.z as f32))
+        WorldPos(Vec3::new(value.0.x as f32, value.0.y as f32 - WORLD_Y_OFFSET, value.0.z as f32))
     }
 }
 
 impl From<WorldPos> for WorldBlockPos {
+    /// Block containing the point. Blocks are centered on their [`WorldPos`].
     fn from(value: WorldPos) -> Self {
-        WorldBlockPos(IVec3::new(value.0.x as i32, value.0.y as i32 + 100, value.0.z as i32))
+        WorldBlockPos((value.0 + Vec3::new(0.5, WORLD_Y_OFFSET + 0.5, 0.5)).floor().as_ivec3())
     }
 }
 